@@ -0,0 +1,295 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::format;
+use crate::interpreter::*;
+use crate::printer;
+use crate::value::{Port, Primitive, Value};
+
+mod lists;
+mod maps;
+mod strings;
+mod symbols;
+mod vectors;
+
+/// Binds the primitive procedures in `environment`.
+pub fn install(environment: &Environment) {
+    for (name, operation) in create_binary_map() {
+        define(environment, name, move |args| arithmetic(operation, args));
+    }
+
+    for (name, operation) in create_logic_map() {
+        match operation {
+            Operation::And | Operation::Or => {
+                define(environment, name, move |args| connective(operation, args))
+            }
+            _ => define(environment, name, move |args| compare(operation, args)),
+        }
+    }
+
+    define(environment, "!", not);
+
+    define(environment, "format", format);
+    define(environment, "open-output-string", |args| {
+        expect_arity(args, 0)?;
+        Ok(Value::Port(Rc::new(Port::String(RefCell::new(
+            String::new(),
+        )))))
+    });
+    define(environment, "get-output-string", |args| match args {
+        [Value::Port(port)] => match &**port {
+            Port::String(buffer) => Ok(Value::Str(buffer.borrow().clone())),
+            Port::Stdout => Err(InterpretError::Expected(
+                "Expected a string port, got the standard output".to_string(),
+            )),
+        },
+        _ => Err(InterpretError::Expected(
+            "Expected (get-output-string port)".to_string(),
+        )),
+    });
+    define(environment, "current-output-port", |args| {
+        expect_arity(args, 0)?;
+        Ok(Value::Port(Rc::new(Port::Stdout)))
+    });
+    define(environment, "display", |args| {
+        output(args, "(display value [port])", printer::display)
+    });
+    define(environment, "write", |args| {
+        output(args, "(write value [port])", printer::write)
+    });
+    define(environment, "newline", |args| {
+        match args {
+            [] => Port::Stdout.write_str("\n"),
+            [Value::Port(port)] => port.write_str("\n"),
+            _ => {
+                return Err(InterpretError::Expected(
+                    "Expected (newline [port])".to_string(),
+                ))
+            }
+        }
+        Ok(Value::Unspecified)
+    });
+
+    define(environment, "assert-equal", |args| match args {
+        [expected, actual] if expected == actual => Ok(Value::Unspecified),
+        [expected, actual] => Err(InterpretError::Expected(format!(
+            "Expected {}, got {}",
+            expected, actual
+        ))),
+        _ => Err(InterpretError::Expected(
+            "Expected (assert-equal expected actual)".to_string(),
+        )),
+    });
+
+    lists::install(environment);
+    maps::install(environment);
+    strings::install(environment);
+    symbols::install(environment);
+    vectors::install(environment);
+}
+
+fn define(
+    environment: &Environment,
+    name: &str,
+    function: impl Fn(&[Value]) -> Result<Value, InterpretError> + 'static,
+) {
+    define_applying(environment, name, move |args, _| function(args));
+}
+
+/// Binds a primitive that calls procedures, like `vector-map`.
+fn define_applying(
+    environment: &Environment,
+    name: &str,
+    function: impl Fn(&[Value], &mut dyn Apply) -> Result<Value, InterpretError> + 'static,
+) {
+    let primitive = Primitive {
+        name: name.to_string(),
+        function: Box::new(function),
+    };
+    environment.define(name.to_string(), Value::Primitive(Rc::new(primitive)));
+}
+
+/// The primitives derived forms call directly, whatever their names are
+/// bound to: `quasiquote` builds its data with these, and `assert-error`
+/// checks its expression with one.
+pub mod derived {
+    use std::rc::Rc;
+
+    use crate::interpreter::{Apply, InterpretError};
+    use crate::value::{Builtin, Primitive, Value};
+
+    pub use super::lists::{append, list};
+    pub use super::maps::list_to_hash;
+    pub use super::vectors::list_to_vector;
+
+    /// `function` as a procedure value.
+    pub fn primitive(name: &str, function: fn(&[Value]) -> Result<Value, InterpretError>) -> Value {
+        applying(name, Box::new(move |args, _| function(args)))
+    }
+
+    /// `function`, which calls procedures, as a procedure value.
+    pub fn applying(name: &str, function: Box<Builtin>) -> Value {
+        Value::Primitive(Rc::new(Primitive {
+            name: name.to_string(),
+            function,
+        }))
+    }
+
+    /// `(assert-error expr)` passes `(lambda () expr)` to this, which fails
+    /// unless calling it raises an error.
+    pub fn assert_error(args: &[Value], apply: &mut dyn Apply) -> Result<Value, InterpretError> {
+        match args {
+            [thunk] => match apply.apply(thunk, vec![]) {
+                Ok(value) => Err(InterpretError::Expected(format!(
+                    "Expected an error, got {}",
+                    value
+                ))),
+                Err(_) => Ok(Value::Unspecified),
+            },
+            _ => Err(super::expected("(assert-error expr)")),
+        }
+    }
+}
+
+fn expected(what: &str) -> InterpretError {
+    InterpretError::Expected(format!("Expected {}", what))
+}
+
+fn expect_arity(args: &[Value], arity: usize) -> Result<(), InterpretError> {
+    if args.len() == arity {
+        Ok(())
+    } else {
+        Err(InterpretError::Expected(format!(
+            "Expected {} arguments, got {}",
+            arity,
+            args.len()
+        )))
+    }
+}
+
+fn numbers(args: &[Value]) -> Result<Vec<f32>, InterpretError> {
+    args.iter()
+        .map(|arg| match arg {
+            Value::Number(number) => Ok(*number),
+            _ => Err(InterpretError::ParseError),
+        })
+        .collect()
+}
+
+fn booleans(args: &[Value]) -> Result<Vec<bool>, InterpretError> {
+    args.iter()
+        .map(|arg| match arg {
+            Value::Bool(boolean) => Ok(*boolean),
+            other => Err(InterpretError::Expected(format!(
+                "Expected a boolean, got {}",
+                other
+            ))),
+        })
+        .collect()
+}
+
+/// Folds the operands left to right; `-` with a single operand negates it.
+fn arithmetic(operation: &Operation, args: &[Value]) -> Result<Value, InterpretError> {
+    match numbers(args)?.as_slice() {
+        [] => Err(InterpretError::IndexOutOfBounds),
+        [operand] if matches!(operation, Operation::Sub) => {
+            Ok(Value::Number(unary(Operation::Sub, *operand)))
+        }
+        [first, rest @ ..] => rest
+            .iter()
+            .try_fold(*first, |left, right| {
+                binary(operation.clone(), left, *right)
+            })
+            .map(Value::Number),
+    }
+}
+
+/// True when every adjacent pair of operands satisfies the comparison.
+fn compare(operation: &Operation, args: &[Value]) -> Result<Value, InterpretError> {
+    let numbers = numbers(args)?;
+    if numbers.len() < 2 {
+        return Err(InterpretError::IndexOutOfBounds);
+    }
+
+    for pair in numbers.windows(2) {
+        if !comparison(operation.clone(), pair[0], pair[1])? {
+            return Ok(Value::Bool(false));
+        }
+    }
+    Ok(Value::Bool(true))
+}
+
+fn connective(operation: &Operation, args: &[Value]) -> Result<Value, InterpretError> {
+    match booleans(args)?.as_slice() {
+        [first, rest @ ..] if !rest.is_empty() => {
+            let mut result = *first;
+            for right in rest {
+                result = logical(operation.clone(), result, *right)?;
+            }
+            Ok(Value::Bool(result))
+        }
+        _ => Err(InterpretError::Expected(format!(
+            "{:?} operation expects two operands",
+            operation
+        ))),
+    }
+}
+
+fn not(args: &[Value]) -> Result<Value, InterpretError> {
+    match args {
+        [Value::Bool(boolean)] => Ok(Value::Bool(!boolean)),
+        [Value::Number(number)] => Ok(Value::Number(unary(Operation::Not, *number))),
+        [other] => Err(InterpretError::Expected(format!(
+            "Expected a boolean or a number, got {}",
+            other
+        ))),
+        _ => expect_arity(args, 1).map(|_| Value::Unspecified),
+    }
+}
+
+/// Writes the first argument, rendered by `render`, to the port given as the
+/// second argument or to the standard output.
+fn output(
+    args: &[Value],
+    usage: &str,
+    render: fn(&Value) -> String,
+) -> Result<Value, InterpretError> {
+    match args {
+        [value] => Port::Stdout.write_str(&render(value)),
+        [value, Value::Port(port)] => port.write_str(&render(value)),
+        _ => return Err(InterpretError::Expected(format!("Expected {}", usage))),
+    }
+    Ok(Value::Unspecified)
+}
+
+/// `(format destination control args...)`: `true` prints to the standard
+/// output, `false` returns the text as a string, and a port is written to.
+/// Without a destination the text is returned.
+fn format(args: &[Value]) -> Result<Value, InterpretError> {
+    let (destination, control, args) = match args {
+        [Value::Str(control), args @ ..] => (&Value::Bool(false), control, args),
+        [destination, Value::Str(control), args @ ..] => (destination, control, args),
+        _ => {
+            return Err(InterpretError::Expected(
+                "Expected (format [destination] control args...)".to_string(),
+            ))
+        }
+    };
+
+    let text = format::format(control, args)?;
+
+    match destination {
+        Value::Bool(false) => Ok(Value::Str(text)),
+        Value::Bool(true) => {
+            Port::Stdout.write_str(&text);
+            Ok(Value::Unspecified)
+        }
+        Value::Port(port) => {
+            port.write_str(&text);
+            Ok(Value::Unspecified)
+        }
+        other => Err(InterpretError::Expected(format!(
+            "Expected true, false or a port as the format destination, got {}",
+            other
+        ))),
+    }
+}
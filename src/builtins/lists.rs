@@ -0,0 +1,54 @@
+use super::{define, expected};
+use crate::interpreter::{Environment, InterpretError};
+use crate::value::Value;
+
+/// Binds the list procedures. Lists are immutable, so `cons` and `cdr` copy.
+pub fn install(environment: &Environment) {
+    define(environment, "list", list);
+    define(environment, "append", append);
+    define(environment, "cons", |args| match args {
+        [head, Value::List(tail)] => {
+            let mut items = vec![head.clone()];
+            items.extend(tail.iter().cloned());
+            Ok(Value::List(items))
+        }
+        _ => Err(expected("(cons value list)")),
+    });
+    define(environment, "car", |args| match args {
+        [Value::List(items)] if !items.is_empty() => Ok(items[0].clone()),
+        _ => Err(expected("(car non-empty-list)")),
+    });
+    define(environment, "cdr", |args| match args {
+        [Value::List(items)] if !items.is_empty() => Ok(Value::List(items[1..].to_vec())),
+        _ => Err(expected("(cdr non-empty-list)")),
+    });
+    define(environment, "null?", |args| match args {
+        [value] => Ok(Value::Bool(
+            matches!(value, Value::List(items) if items.is_empty()),
+        )),
+        _ => Err(expected("(null? value)")),
+    });
+}
+
+/// `(list value...)`
+pub fn list(args: &[Value]) -> Result<Value, InterpretError> {
+    Ok(Value::List(args.to_vec()))
+}
+
+/// `(append list...)`: the elements of the lists, one list after another.
+pub fn append(args: &[Value]) -> Result<Value, InterpretError> {
+    let mut items = vec![];
+    for arg in args {
+        match arg {
+            Value::List(list) => items.extend(list.iter().cloned()),
+            other => {
+                return Err(InterpretError::Expected(format!(
+                    "Expected a list, got {}",
+                    other
+                )))
+            }
+        }
+    }
+
+    Ok(Value::List(items))
+}
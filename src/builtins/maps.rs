@@ -0,0 +1,240 @@
+use super::{define, define_applying, expect_arity, expected};
+use crate::interpreter::{Apply, Environment, InterpretError};
+use crate::map::Map;
+use crate::value::Value;
+
+/// Binds the hash map procedures. Keys are compared by contents, so any value
+/// can be one, and entries stay in the order their keys were added.
+pub fn install(environment: &Environment) {
+    define(environment, "hash?", |args| {
+        expect_arity(args, 1)?;
+        Ok(Value::Bool(matches!(args[0], Value::Map(_))))
+    });
+    define(environment, "hash", hash);
+    // Without a default, a missing key is an error
+    define(environment, "hash-ref", |args| match args {
+        [Value::Map(map), key] => map
+            .borrow()
+            .get(key)
+            .cloned()
+            .ok_or_else(|| InterpretError::Expected(format!("Key {} is not in the map", key))),
+        [Value::Map(map), key, default] => Ok(map.borrow().get(key).unwrap_or(default).clone()),
+        _ => Err(expected("(hash-ref map key [default])")),
+    });
+    define(environment, "hash-has-key?", |args| match args {
+        [Value::Map(map), key] => Ok(Value::Bool(map.borrow().get(key).is_some())),
+        _ => Err(expected("(hash-has-key? map key)")),
+    });
+    define(environment, "hash-set!", |args| match args {
+        [Value::Map(map), key, value] => {
+            Map::set(map, key.clone(), value.clone());
+            Ok(Value::Unspecified)
+        }
+        _ => Err(expected("(hash-set! map key value)")),
+    });
+    define(environment, "hash-remove!", |args| match args {
+        [Value::Map(map), key] => {
+            Map::delete(map, key);
+            Ok(Value::Unspecified)
+        }
+        _ => Err(expected("(hash-remove! map key)")),
+    });
+    define(environment, "hash-count", |args| match args {
+        [Value::Map(map)] => Ok(Value::Number(map.borrow().len() as f32)),
+        _ => Err(expected("(hash-count map)")),
+    });
+    define(environment, "hash-keys", |args| match args {
+        [Value::Map(map)] => Ok(Value::List(map.borrow().keys().cloned().collect())),
+        _ => Err(expected("(hash-keys map)")),
+    });
+    define(environment, "hash-values", |args| match args {
+        [Value::Map(map)] => Ok(Value::List(map.borrow().values().cloned().collect())),
+        _ => Err(expected("(hash-values map)")),
+    });
+    define(environment, "hash->list", |args| match args {
+        [Value::Map(map)] => Ok(Value::List(
+            map.borrow()
+                .iter()
+                .map(|(key, value)| Value::List(vec![key.clone(), value.clone()]))
+                .collect(),
+        )),
+        _ => Err(expected("(hash->list map)")),
+    });
+    define_applying(environment, "hash-map", |args, apply| {
+        let results = each_entry("(hash-map procedure map)", args, apply)?;
+        Ok(Value::List(results))
+    });
+    define_applying(environment, "hash-for-each", |args, apply| {
+        each_entry("(hash-for-each procedure map)", args, apply)?;
+        Ok(Value::Unspecified)
+    });
+}
+
+/// `(hash key value ...)`
+fn hash(args: &[Value]) -> Result<Value, InterpretError> {
+    if !args.len().is_multiple_of(2) {
+        return Err(expected("(hash key value ...)"));
+    }
+    Ok(Value::map(
+        args.chunks(2)
+            .map(|entry| (entry[0].clone(), entry[1].clone()))
+            .collect(),
+    ))
+}
+
+/// A map from a list of keys and values, alternating, as a quasiquoted map
+/// template builds them.
+pub fn list_to_hash(args: &[Value]) -> Result<Value, InterpretError> {
+    match args {
+        [Value::List(items)] => hash(items),
+        _ => Err(expected("(hash key value ...)")),
+    }
+}
+
+/// Applies the procedure in `args` to the key and value of each entry of the
+/// map in `args`, in order, and collects the results.
+fn each_entry(
+    usage: &str,
+    args: &[Value],
+    apply: &mut dyn Apply,
+) -> Result<Vec<Value>, InterpretError> {
+    let (procedure, map) = match args {
+        [procedure, Value::Map(map)] => (procedure, map),
+        _ => return Err(expected(usage)),
+    };
+
+    // Copied first, so the procedure is free to modify the map
+    let entries: Map = map.borrow().clone();
+    entries
+        .iter()
+        .map(|(key, value)| apply.apply(procedure, vec![key.clone(), value.clone()]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::{run, InterpretError};
+
+    #[test]
+    fn literals_are_constants() {
+        let source = r#"
+            {:a 1 :b "two" :c (three)}
+            {}
+            (hash? {:a 1})
+            (hash? '(:a 1))
+            (hash 'x (+ 1 2) 'x 4)"#;
+
+        assert_eq!(
+            run(source),
+            Ok(r#"{:a 1 :b "two" :c (three)} {} true false {x 4}"#.to_string())
+        );
+    }
+
+    #[test]
+    fn looking_up_and_updating() {
+        let source = "
+            (define m (hash :a 1 :b 2))
+            (hash-set! m :c 3)
+            (hash-set! m :a 10)
+            (hash-remove! m :b)
+            (hash-remove! m :missing)
+            m
+            (hash-ref m :a)
+            (hash-ref m :b 'none)
+            (hash-has-key? m :c)
+            (hash-count m)
+            (hash-keys m)
+            (hash-values m)";
+
+        assert_eq!(
+            run(source),
+            Ok("{:a 10 :c 3} 10 none true 2 (:a :c) (10 3)".to_string())
+        );
+        assert_eq!(
+            run("(hash-ref {:a 1} :b)"),
+            Err(InterpretError::Expected(
+                "Key :b is not in the map".to_string()
+            ))
+        );
+        assert!(run("(hash :a)").is_err());
+        assert!(run("(hash-set! '(a 1) :a 2)").is_err());
+    }
+
+    #[test]
+    fn any_value_can_be_a_key() {
+        let source = r#"
+            (define m (hash))
+            (hash-set! m '(1 2) 'list)
+            (hash-set! m [1 2] 'vector)
+            (hash-set! m {:x 1 :y 2} 'map)
+            (hash-set! m "1 2" 'string)
+            (hash-set! m 0 'zero)
+            (hash-ref m (list->vector '(1 2)))
+            (hash-ref m '(1 2))
+            (hash-ref m {:y 2 :x 1})
+            (hash-ref m "1 2")
+            (hash-ref m -0)
+            (hash-count m)"#;
+
+        assert_eq!(run(source), Ok("vector list map string zero 5".to_string()));
+    }
+
+    #[test]
+    fn maps_and_vectors_containing_themselves() {
+        let source = "
+            (define m {})
+            (hash-set! m m 1)
+            (hash-ref m m)
+            (define v (vector 0))
+            (vector-set! v 0 v)
+            (define w (vector 0))
+            (vector-set! w 0 w)
+            (define h (hash))
+            (hash-set! h v 'cyclic)
+            (hash-ref h w)
+            (assert-equal v w)
+            (hash-remove! m m)
+            (hash-count m)";
+
+        assert_eq!(run(source), Ok("1 cyclic 0".to_string()));
+        assert!(run("(define v [1]) (vector-set! v 0 v) (assert-equal v [[2]])").is_err());
+    }
+
+    #[test]
+    fn each_evaluation_of_a_literal_is_a_new_map() {
+        let source = "
+            (define f () {:a 1 :inner {:b 2}})
+            (hash-set! (f) :a 99)
+            (hash-set! (hash-ref (f) :inner) :b 99)
+            (f)";
+
+        assert_eq!(run(source), Ok("{:a 1 :inner {:b 2}}".to_string()));
+    }
+
+    #[test]
+    fn maps_are_shared_and_compared_by_contents() {
+        let source = "
+            (define a {:x 1})
+            (define b a)
+            (hash-set! b :y 2)
+            (assert-equal {:y 2 :x 1} a)
+            a";
+
+        assert_eq!(run(source), Ok("{:x 1 :y 2}".to_string()));
+        assert!(run("(assert-equal {:x 1} {:x 2})").is_err());
+    }
+
+    #[test]
+    fn iterating() {
+        let source = "
+            (define m {:a 1 :b 2})
+            (hash-map (lambda (k v) (* v 10)) m)
+            (hash->list m)
+            (define total 0)
+            (hash-for-each (lambda (k v) (set! total (+ total v)) (hash-remove! m k)) m)
+            total
+            m";
+
+        assert_eq!(run(source), Ok("(10 20) ((:a 1) (:b 2)) 3 {}".to_string()));
+    }
+}
@@ -0,0 +1,234 @@
+use super::{define, expect_arity, expected};
+use crate::interpreter::{Environment, InterpretError};
+use crate::tokenizer::parse_number;
+use crate::value::Value;
+
+/// Binds the string procedures. Lengths and indices count characters, not
+/// bytes, so they work the same on any Unicode text.
+pub fn install(environment: &Environment) {
+    define(environment, "string?", |args| {
+        expect_arity(args, 1)?;
+        Ok(Value::Bool(matches!(args[0], Value::Str(_))))
+    });
+    define(environment, "string-length", |args| match args {
+        [Value::Str(string)] => Ok(Value::Number(string.chars().count() as f32)),
+        _ => Err(expected("(string-length string)")),
+    });
+    define(environment, "substring", substring);
+    define(environment, "string-append", |args| {
+        Ok(Value::Str(strings(args)?.concat()))
+    });
+    define(environment, "string-split", |args| match args {
+        [Value::Str(string)] => Ok(string_list(string.split_whitespace())),
+        [Value::Str(_), Value::Str(separator)] if separator.is_empty() => {
+            Err(expected("a non-empty separator"))
+        }
+        [Value::Str(string), Value::Str(separator)] => {
+            Ok(string_list(string.split(separator.as_str())))
+        }
+        _ => Err(expected("(string-split string [separator])")),
+    });
+    define(environment, "string-join", |args| {
+        let (items, separator) = match args {
+            [Value::List(items)] => (items, " "),
+            [Value::List(items), Value::Str(separator)] => (items, separator.as_str()),
+            _ => return Err(expected("(string-join list [separator])")),
+        };
+        Ok(Value::Str(strings(items)?.join(separator)))
+    });
+    define(environment, "string-upcase", |args| match args {
+        [Value::Str(string)] => Ok(Value::Str(string.to_uppercase())),
+        _ => Err(expected("(string-upcase string)")),
+    });
+    define(environment, "string-downcase", |args| match args {
+        [Value::Str(string)] => Ok(Value::Str(string.to_lowercase())),
+        _ => Err(expected("(string-downcase string)")),
+    });
+    define(environment, "string-trim", |args| match args {
+        [Value::Str(string)] => Ok(Value::Str(string.trim().to_string())),
+        _ => Err(expected("(string-trim string)")),
+    });
+    // The character index of the first match, or false
+    define(environment, "string-contains", |args| match args {
+        [Value::Str(string), Value::Str(pattern)] => Ok(match string.find(pattern.as_str()) {
+            Some(byte) => Value::Number(string[..byte].chars().count() as f32),
+            None => Value::Bool(false),
+        }),
+        _ => Err(expected("(string-contains string pattern)")),
+    });
+    // Numbers as the reader reads them; false for anything else, or for a
+    // number too large to be finite
+    define(environment, "string->number", |args| match args {
+        [Value::Str(string)] => Ok(match parse_number(string.trim()) {
+            Some(number) if number.is_finite() => Value::Number(number),
+            _ => Value::Bool(false),
+        }),
+        _ => Err(expected("(string->number string)")),
+    });
+    define(environment, "number->string", |args| match args {
+        [Value::Number(number)] => Ok(Value::Str(number.to_string())),
+        _ => Err(expected("(number->string number)")),
+    });
+    define(environment, "string=?", |args| compare(args, |a, b| a == b));
+    define(environment, "string<?", |args| compare(args, |a, b| a < b));
+}
+
+fn strings(args: &[Value]) -> Result<Vec<&str>, InterpretError> {
+    args.iter()
+        .map(|arg| match arg {
+            Value::Str(string) => Ok(string.as_str()),
+            other => Err(InterpretError::Expected(format!(
+                "Expected a string, got {}",
+                other
+            ))),
+        })
+        .collect()
+}
+
+fn string_list<'a>(parts: impl Iterator<Item = &'a str>) -> Value {
+    Value::List(parts.map(|part| Value::Str(part.to_string())).collect())
+}
+
+/// `(substring string start [end])`, with `end` defaulting to the length.
+fn substring(args: &[Value]) -> Result<Value, InterpretError> {
+    let (string, start, end) = match args {
+        [Value::Str(string), Value::Number(start)] => (string, *start, None),
+        [Value::Str(string), Value::Number(start), Value::Number(end)] => {
+            (string, *start, Some(*end))
+        }
+        _ => return Err(expected("(substring string start [end])")),
+    };
+
+    let length = string.chars().count();
+    let (start, end) = match (
+        index(start, length),
+        end.map_or(Some(length), |end| index(end, length)),
+    ) {
+        (Some(start), Some(end)) if start <= end => (start, end),
+        _ => {
+            return Err(InterpretError::Expected(format!(
+                "Substring range {}..{} is out of bounds for a string of length {}",
+                start,
+                end.map_or(length.to_string(), |end| end.to_string()),
+                length
+            )))
+        }
+    };
+
+    Ok(Value::Str(
+        string.chars().skip(start).take(end - start).collect(),
+    ))
+}
+
+/// `number` as a character index into a string of `length` characters; the
+/// length itself is allowed, as the end of a range.
+fn index(number: f32, length: usize) -> Option<usize> {
+    (number >= 0.0 && number.fract() == 0.0 && number as usize <= length).then_some(number as usize)
+}
+
+/// True when every adjacent pair of strings satisfies `holds`.
+fn compare(args: &[Value], holds: fn(&str, &str) -> bool) -> Result<Value, InterpretError> {
+    let strings = strings(args)?;
+    if strings.len() < 2 {
+        return Err(expected("at least two strings to compare"));
+    }
+
+    Ok(Value::Bool(
+        strings.windows(2).all(|pair| holds(pair[0], pair[1])),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::{run, InterpretError};
+
+    #[test]
+    fn lengths_and_indices_count_characters() {
+        let source = r#"
+            (string-length "héllo wörld")
+            (substring "héllo wörld" 6)
+            (substring "日本語" 1 2)
+            (string-contains "naïve café" "café")
+            (string-contains "abc" "z")"#;
+
+        assert_eq!(run(source), Ok(r#"11 "wörld" "本" 6 false"#.to_string()));
+    }
+
+    #[test]
+    fn building_and_splitting() {
+        let source = r#"
+            (string-append "foo" "-" "bar")
+            (string-split "a,b,,c" ",")
+            (string-split "  one  two ")
+            (string-join (string-split "a b c") ", ")
+            (string-trim "  padded  ")"#;
+
+        assert_eq!(
+            run(source),
+            Ok(r#""foo-bar" ("a" "b" "" "c") ("one" "two") "a, b, c" "padded""#.to_string())
+        );
+    }
+
+    #[test]
+    fn case_conversion_and_comparison() {
+        let source = r#"
+            (string-upcase "straße")
+            (string-downcase "ÀÉÎ")
+            (string=? "a" "a" "a")
+            (string<? "apple" "banana" "cherry")
+            (string<? "b" "a")"#;
+
+        assert_eq!(
+            run(source),
+            Ok(r#""STRASSE" "àéî" true true false"#.to_string())
+        );
+    }
+
+    #[test]
+    fn numbers_and_strings() {
+        let source = r#"
+            (string->number "42")
+            (string->number "1.5")
+            (string->number "forty")
+            (number->string 7)
+            (string->number " -.5 ")
+            (string->number "nan")
+            (string->number "infinity")
+            (string->number "-inf")
+            (string->number "1e40")
+            (string->number "+")"#;
+
+        assert_eq!(
+            run(source),
+            Ok(r#"42 1.5 false "7" -0.5 false false false false false"#.to_string())
+        );
+    }
+
+    #[test]
+    fn escapes_and_raw_strings() {
+        let source = r###"
+            (string-length "a\nb")
+            (string-append "say " #r#""hi""#)"###;
+
+        assert_eq!(run(source), Ok(r#"3 "say \"hi\"""#.to_string()));
+    }
+
+    #[test]
+    fn substring_bounds_are_checked() {
+        assert_eq!(
+            run(r#"(substring "abc" 2 5)"#),
+            Err(InterpretError::Expected(
+                "Substring range 2..5 is out of bounds for a string of length 3".to_string()
+            ))
+        );
+        assert_eq!(
+            run(r#"(substring "abc" -1)"#),
+            Err(InterpretError::Expected(
+                "Substring range -1..3 is out of bounds for a string of length 3".to_string()
+            ))
+        );
+        assert!(run(r#"(substring "abc" 0.5 2)"#).is_err());
+        assert!(run(r#"(substring "abc" 2 1)"#).is_err());
+        assert_eq!(run(r#"(substring "abc" 3)"#), Ok(r#""""#.to_string()));
+    }
+}
@@ -0,0 +1,76 @@
+use super::{define, expect_arity, expected};
+use crate::interpreter::Environment;
+use crate::symbol::Symbol;
+use crate::value::Value;
+
+/// Binds the symbol and keyword procedures.
+pub fn install(environment: &Environment) {
+    define(environment, "symbol?", |args| {
+        expect_arity(args, 1)?;
+        Ok(Value::Bool(matches!(args[0], Value::Symbol(_))))
+    });
+    define(environment, "keyword?", |args| {
+        expect_arity(args, 1)?;
+        Ok(Value::Bool(matches!(args[0], Value::Keyword(_))))
+    });
+    define(environment, "symbol->string", |args| match args {
+        [Value::Symbol(symbol)] => Ok(Value::Str(symbol.name().to_string())),
+        _ => Err(expected("(symbol->string symbol)")),
+    });
+    define(environment, "string->symbol", |args| match args {
+        [Value::Str(name)] => Ok(Value::symbol(name)),
+        _ => Err(expected("(string->symbol string)")),
+    });
+    define(environment, "gensym", |args| match args {
+        [] => Ok(Value::Symbol(Symbol::gensym("g"))),
+        [Value::Str(prefix)] => Ok(Value::Symbol(Symbol::gensym(prefix))),
+        [Value::Symbol(prefix)] => Ok(Value::Symbol(Symbol::gensym(prefix.name()))),
+        _ => Err(expected("(gensym [prefix])")),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::{run, InterpretError};
+
+    #[test]
+    fn keywords_evaluate_to_themselves() {
+        let source = "
+            :name
+            (keyword? :name)
+            (keyword? 'name)
+            (symbol? 'name)
+            (symbol? :name)
+            (symbol? \"name\")
+            (assert-equal :a ':a)";
+
+        assert_eq!(
+            run(source),
+            Ok(":name true false true false false".to_string())
+        );
+        assert!(run("(assert-equal :a 'a)").is_err());
+        assert!(run("(define :a 1)").is_err());
+    }
+
+    #[test]
+    fn converting_between_symbols_and_strings() {
+        let source = r#"
+            (symbol->string 'apple)
+            (string->symbol "pear")
+            (assert-equal 'pear (string->symbol "pear"))
+            (symbol? (string->symbol "with space"))"#;
+
+        assert_eq!(run(source), Ok(r#""apple" pear true"#.to_string()));
+        assert!(run("(symbol->string :apple)").is_err());
+        assert!(run("(string->symbol 'apple)").is_err());
+    }
+
+    #[test]
+    fn unbound_variables_are_errors() {
+        assert_eq!(
+            run("(define total 1) (+ totl 1)"),
+            Err(InterpretError::Unbound("totl".to_string()))
+        );
+        assert_eq!(run("'totl"), Ok("totl".to_string()));
+    }
+}
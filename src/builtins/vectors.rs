@@ -0,0 +1,240 @@
+use super::{define, define_applying, expect_arity, expected};
+use crate::interpreter::{Apply, Environment, InterpretError};
+use crate::value::Value;
+
+/// Binds the vector procedures. Indices start at 0.
+pub fn install(environment: &Environment) {
+    define(environment, "vector?", |args| {
+        expect_arity(args, 1)?;
+        Ok(Value::Bool(matches!(args[0], Value::Vector(_))))
+    });
+    // Elements default to 0
+    define(environment, "make-vector", |args| match args {
+        [Value::Number(length)] => filled(*length, Value::Number(0.0)),
+        [Value::Number(length), fill] => filled(*length, fill.clone()),
+        _ => Err(expected("(make-vector length [fill])")),
+    });
+    define(environment, "vector", |args| {
+        Ok(Value::vector(args.to_vec()))
+    });
+    define(environment, "vector-length", |args| match args {
+        [Value::Vector(items)] => Ok(Value::Number(items.borrow().len() as f32)),
+        _ => Err(expected("(vector-length vector)")),
+    });
+    define(environment, "vector-ref", |args| match args {
+        [Value::Vector(items), Value::Number(index)] => {
+            let items = items.borrow();
+            Ok(items[position(*index, items.len())?].clone())
+        }
+        _ => Err(expected("(vector-ref vector index)")),
+    });
+    define(environment, "vector-set!", |args| match args {
+        [Value::Vector(items), Value::Number(index), value] => {
+            let mut items = items.borrow_mut();
+            let index = position(*index, items.len())?;
+            items[index] = value.clone();
+            Ok(Value::Unspecified)
+        }
+        _ => Err(expected("(vector-set! vector index value)")),
+    });
+    define(environment, "vector->list", |args| match args {
+        [Value::Vector(items)] => Ok(Value::List(items.borrow().clone())),
+        _ => Err(expected("(vector->list vector)")),
+    });
+    define(environment, "list->vector", list_to_vector);
+    define_applying(environment, "vector-map", vector_map);
+}
+
+/// `(list->vector list)`
+pub fn list_to_vector(args: &[Value]) -> Result<Value, InterpretError> {
+    match args {
+        [Value::List(items)] => Ok(Value::vector(items.clone())),
+        _ => Err(expected("(list->vector list)")),
+    }
+}
+
+/// The longest vector: past 2^24, numbers can't tell every index apart.
+const MAX_LENGTH: usize = 1 << 24;
+
+/// `number` as a number of elements.
+fn count(number: f32) -> Result<usize, InterpretError> {
+    if number < 0.0 || number.fract() != 0.0 {
+        return Err(InterpretError::Expected(format!(
+            "Expected a length, got {}",
+            number
+        )));
+    }
+    if number as usize > MAX_LENGTH {
+        return Err(InterpretError::Expected(format!(
+            "A vector of length {} is longer than the maximum of {}",
+            number, MAX_LENGTH
+        )));
+    }
+
+    Ok(number as usize)
+}
+
+/// A vector of `length` copies of `fill`.
+fn filled(length: f32, fill: Value) -> Result<Value, InterpretError> {
+    let length = count(length)?;
+    let mut items = Vec::new();
+    items.try_reserve_exact(length).map_err(|_| {
+        InterpretError::Expected(format!(
+            "Not enough memory for a vector of length {}",
+            length
+        ))
+    })?;
+
+    items.resize(length, fill);
+    Ok(Value::vector(items))
+}
+
+/// `number` as an index into a vector of `length` elements.
+fn position(number: f32, length: usize) -> Result<usize, InterpretError> {
+    if number < 0.0 || number.fract() != 0.0 || number as usize >= length {
+        return Err(InterpretError::Expected(format!(
+            "Index {} is out of bounds for a vector of length {}",
+            number, length
+        )));
+    }
+
+    Ok(number as usize)
+}
+
+/// `(vector-map procedure vector...)`: a new vector of `procedure` applied to
+/// the elements at each index, as far as the shortest vector goes.
+fn vector_map(args: &[Value], apply: &mut dyn Apply) -> Result<Value, InterpretError> {
+    let (procedure, vectors) = match args {
+        [procedure, vectors @ ..] if !vectors.is_empty() => (procedure, vectors),
+        _ => return Err(expected("(vector-map procedure vector...)")),
+    };
+
+    // Copied first, so the procedure is free to modify the vectors
+    let vectors = vectors
+        .iter()
+        .map(|vector| match vector {
+            Value::Vector(items) => Ok(items.borrow().clone()),
+            other => Err(InterpretError::Expected(format!(
+                "Expected a vector, got {}",
+                other
+            ))),
+        })
+        .collect::<Result<Vec<_>, InterpretError>>()?;
+    let length = vectors.iter().map(Vec::len).min().unwrap_or_default();
+
+    (0..length)
+        .map(|index| {
+            let args = vectors.iter().map(|items| items[index].clone()).collect();
+            apply.apply(procedure, args)
+        })
+        .collect::<Result<Vec<_>, InterpretError>>()
+        .map(Value::vector)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::{run, InterpretError};
+
+    #[test]
+    fn literals_are_constants() {
+        let source = r#"
+            #(1 "two" (three) #(4))
+            [a (+ 1 2)]
+            '#(x)
+            (vector? [1])
+            (vector? '(1))"#;
+
+        assert_eq!(
+            run(source),
+            Ok(r#"#(1 "two" (three) #(4)) #(a (+ 1 2)) #(x) true false"#.to_string())
+        );
+    }
+
+    #[test]
+    fn building_and_indexing() {
+        let source = "
+            (define v (make-vector 3))
+            (vector-set! v 1 (vector 'a (+ 1 1)))
+            v
+            (vector-ref v 1)
+            (vector-length v)
+            (make-vector 2 'x)
+            (vector)";
+
+        assert_eq!(
+            run(source),
+            Ok("#(0 #(a 2) 0) #(a 2) 3 #(x x) #()".to_string())
+        );
+    }
+
+    #[test]
+    fn indices_are_checked() {
+        assert_eq!(
+            run("(vector-ref [1 2 3] 3)"),
+            Err(InterpretError::Expected(
+                "Index 3 is out of bounds for a vector of length 3".to_string()
+            ))
+        );
+        assert!(run("(vector-set! [1] -1 0)").is_err());
+        assert!(run("(vector-ref [1] 0.5)").is_err());
+        assert!(run("(make-vector -2)").is_err());
+        assert_eq!(
+            run("(make-vector 1e30 0)"),
+            Err(InterpretError::Expected(
+                "A vector of length 1000000000000000000000000000000 is longer than the maximum of 16777216"
+                    .to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn each_evaluation_of_a_literal_is_a_new_vector() {
+        let source = "
+            (define f () [1 [2]])
+            (vector-set! (f) 0 99)
+            (vector-set! (vector-ref (f) 1) 0 99)
+            (f)";
+
+        assert_eq!(run(source), Ok("#(1 #(2))".to_string()));
+    }
+
+    #[test]
+    fn vectors_are_shared_and_compared_by_contents() {
+        let source = "
+            (define a (vector 1 2))
+            (define b a)
+            (vector-set! b 0 'changed)
+            a
+            (assert-equal [changed 2] a)";
+
+        assert_eq!(run(source), Ok("#(changed 2)".to_string()));
+        assert!(run("(assert-equal [1 2] [1 2 3])").is_err());
+    }
+
+    #[test]
+    fn conversions_and_mapping() {
+        let source = "
+            (vector->list [1 2 3])
+            (list->vector (vector->list #()))
+            (list->vector '(a b))
+            (vector-map (lambda (x) (* x x)) [1 2 3])
+            (vector-map + [1 2 3] [10 20])";
+
+        assert_eq!(
+            run(source),
+            Ok("(1 2 3) #() #(a b) #(1 4 9) #(11 22)".to_string())
+        );
+    }
+
+    #[test]
+    fn vectors_in_macros() {
+        let source = "
+            (define-syntax swap-pair
+                (syntax-rules () ((_ #(a b)) [b a])))
+            (swap-pair #(1 2))
+            (defmacro tagged (x) `#(tag ,x ,@(quote (rest))))
+            (tagged 5)";
+
+        assert_eq!(run(source), Ok("#(2 1) #(tag 5 rest)".to_string()));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::interpreter::InterpretError;
+use crate::printer;
+use crate::value::Value;
+
+/// The largest width or precision a directive may ask for, so a typo cannot
+/// pad the output with gigabytes of spaces.
+pub const MAX_PARAMETER: usize = 1000;
+
+/// Expands the Common Lisp style directives in `control`:
+///
+/// - `~a` the next argument as `display` shows it
+/// - `~s` the next argument as it would be read back (strings keep their quotes)
+/// - `~d` the next argument, which must be an integer
+/// - `~f` the next argument as a decimal; `~,2f` keeps two digits after the point
+/// - `~%` a newline, `~~` a tilde
+///
+/// A leading number is the minimum width, e.g. `~5d` or `~8,3f`; numbers are
+/// padded on the left, everything else on the right. Widths and precisions
+/// go up to `MAX_PARAMETER`.
+pub fn format(control: &str, args: &[Value]) -> Result<String, InterpretError> {
+    let mut output = String::new();
+    let mut args = args.iter();
+    let mut chars = control.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '~' {
+            output.push(c);
+            continue;
+        }
+
+        // Prefix parameters: ~width,precisionX
+        let mut params: Vec<Option<usize>> = vec![None];
+        while let Some(&next) = chars.peek() {
+            match next {
+                '0'..='9' => {
+                    let digit = next.to_digit(10).unwrap() as usize;
+                    let param = params.last_mut().unwrap();
+                    *param = param
+                        .unwrap_or(0)
+                        .checked_mul(10)
+                        .and_then(|param| param.checked_add(digit))
+                        .filter(|&param| param <= MAX_PARAMETER)
+                        .map(Some)
+                        .ok_or_else(|| {
+                            InterpretError::Expected(format!(
+                                "Format parameters can be at most {}",
+                                MAX_PARAMETER
+                            ))
+                        })?;
+                }
+                ',' => params.push(None),
+                _ => break,
+            }
+            chars.next();
+        }
+        let width = params[0].unwrap_or(0);
+        let precision = params.get(1).copied().flatten();
+
+        let directive = match chars.next() {
+            Some(directive) => directive.to_ascii_lowercase(),
+            None => {
+                return Err(InterpretError::Expected(
+                    "Expected a directive after ~".to_string(),
+                ))
+            }
+        };
+
+        let mut next_arg = || {
+            args.next().ok_or_else(|| {
+                InterpretError::Expected(format!("Missing argument for ~{}", directive))
+            })
+        };
+
+        match directive {
+            'a' => output.push_str(&format!("{:<width$}", printer::display(next_arg()?))),
+            's' => output.push_str(&format!("{:<width$}", printer::write(next_arg()?))),
+            'd' => {
+                let number = expect_number(next_arg()?, directive)?;
+                if number.fract() != 0.0 {
+                    return Err(InterpretError::Expected(format!(
+                        "~d expects an integer, got {}",
+                        number
+                    )));
+                }
+                output.push_str(&format!("{:>width$}", number));
+            }
+            'f' => {
+                let number = expect_number(next_arg()?, directive)?;
+                match precision {
+                    Some(precision) => output.push_str(&format!("{:>width$.precision$}", number)),
+                    None => output.push_str(&format!("{:>width$}", number)),
+                }
+            }
+            '%' => output.push('\n'),
+            '~' => output.push('~'),
+            other => {
+                return Err(InterpretError::Expected(format!(
+                    "Unknown format directive ~{}",
+                    other
+                )))
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+fn expect_number(value: &Value, directive: char) -> Result<f32, InterpretError> {
+    match value {
+        Value::Number(number) => Ok(*number),
+        other => Err(InterpretError::Expected(format!(
+            "~{} expects a number, got {}",
+            directive, other
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::run;
+
+    #[test]
+    fn directives() {
+        let args = [
+            Value::Str("Ada".to_string()),
+            Value::Str("Ada".to_string()),
+            Value::Number(36.0),
+        ];
+
+        assert_eq!(
+            format("Hello ~a (~s), you are ~d years old~%~~", &args),
+            Ok("Hello Ada (\"Ada\"), you are 36 years old\n~".to_string())
+        );
+    }
+
+    #[test]
+    fn width_and_precision() {
+        let args = [
+            Value::Number(1.23456),
+            Value::Number(2.5),
+            Value::Number(7.0),
+        ];
+
+        assert_eq!(
+            format("~,2f|~6,1F|~3d|", &args),
+            Ok("1.23|   2.5|  7|".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_bad_directives_and_arguments() {
+        assert_eq!(
+            format("~a ~a", &[Value::Number(1.0)]),
+            Err(InterpretError::Expected(
+                "Missing argument for ~a".to_string()
+            ))
+        );
+        assert_eq!(
+            format("~q", &[]),
+            Err(InterpretError::Expected(
+                "Unknown format directive ~q".to_string()
+            ))
+        );
+        assert_eq!(
+            format("~d", &[Value::Bool(true)]),
+            Err(InterpretError::Expected(
+                "~d expects a number, got true".to_string()
+            ))
+        );
+        assert_eq!(
+            format("~d", &[Value::Number(3.5)]),
+            Err(InterpretError::Expected(
+                "~d expects an integer, got 3.5".to_string()
+            ))
+        );
+        assert_eq!(
+            format("~99999999999999999999a", &[Value::Number(1.0)]),
+            Err(InterpretError::Expected(
+                "Format parameters can be at most 1000".to_string()
+            ))
+        );
+        assert!(format("~,1001f", &[Value::Number(1.0)]).is_err());
+        assert_eq!(
+            format("~1000a", &[Value::Number(1.0)]).map(|output| output.len()),
+            Ok(1000)
+        );
+    }
+
+    #[test]
+    fn destinations() {
+        let source = r#"
+            (define name "Ada")
+            (format "Hi ~a" name)
+            (format false "~d + ~d" 1 2)
+            (define port (open-output-string))
+            (format port "one ")
+            (format port "~a" 2)
+            (get-output-string port)"#;
+
+        assert_eq!(run(source), Ok(r#""Hi Ada" "1 + 2" "one 2""#.to_string()));
+    }
+
+    #[test]
+    fn format_is_an_ordinary_name() {
+        let source = "
+            (let ((format 3)) format)
+            (define f (format) (format 1))
+            (f (lambda (x) (+ x 1)))";
+
+        assert_eq!(run(source), Ok("3 2".to_string()));
+    }
+}
@@ -0,0 +1,408 @@
+use crate::syntax::{self, NodeKind, SyntaxElement, SyntaxNode};
+use crate::tokenizer::SyntaxError;
+use crate::tokens::TokenType;
+
+/// Lines are kept to this many characters wherever the atoms allow it.
+pub const MAX_WIDTH: usize = 80;
+
+/// Pretty-prints `source` in the canonical layout, or returns its syntax
+/// errors untouched.
+///
+/// A form that fits in the remaining width stays on one line. Otherwise
+/// special forms keep their head and distinguished arguments (the name and
+/// parameters of a `define`, the bindings of a `let`, the test of an `if`)
+/// on the first line and indent the body by 2, while calls align their
+/// arguments under the first one. Comments are kept, trailing ones on the
+/// line they trailed, and runs of blank lines shrink to one.
+pub fn format_source(source: &str, width: usize) -> Result<String, Vec<SyntaxError>> {
+    let (tree, errors) = syntax::parse(source);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut printer = Printer {
+        out: String::new(),
+        width,
+        needs_newline: false,
+    };
+    printer.write_items(&items(&tree), |_| false, 0);
+
+    if !printer.out.is_empty() {
+        printer.out.push('\n');
+    }
+    Ok(printer.out)
+}
+
+/// A datum or comment in a list or at the top level, with how it sat in the
+/// source.
+struct Item {
+    element: SyntaxElement,
+    /// Nothing but spaces between it and what came before.
+    trailing: bool,
+    /// At least one blank line between it and what came before.
+    blank_before: bool,
+}
+
+impl Item {
+    fn is_comment(&self) -> bool {
+        matches!(&self.element, SyntaxElement::Token(token) if token.kind() == TokenType::COMMENT)
+    }
+}
+
+/// The node's children minus whitespace and delimiters.
+fn items(node: &SyntaxNode) -> Vec<Item> {
+    let mut items: Vec<Item> = vec![];
+    let mut newlines = 0;
+
+    for child in node.children() {
+        if let SyntaxElement::Token(token) = &child {
+            match token.kind() {
+                TokenType::WHITESPACE => {
+                    newlines += token.text().matches('\n').count();
+                    continue;
+                }
+                kind if kind.is_open() || kind.is_close() => continue,
+                _ => {}
+            }
+        }
+
+        items.push(Item {
+            element: child,
+            trailing: newlines == 0 && !items.is_empty(),
+            blank_before: newlines >= 2 && !items.is_empty(),
+        });
+        newlines = 0;
+    }
+
+    items
+}
+
+/// The element on a single line, if it can go on one.
+fn flat(element: &SyntaxElement) -> Option<String> {
+    match element {
+        SyntaxElement::Token(token) => {
+            let text = token.text();
+            let line_comment = token.kind() == TokenType::COMMENT && text.starts_with(';');
+
+            match line_comment || text.contains('\n') {
+                true => None,
+                false => Some(text.to_string()),
+            }
+        }
+        SyntaxElement::Node(node) => {
+            let parts = items(node)
+                .iter()
+                .map(|item| flat(&item.element))
+                .collect::<Option<Vec<_>>>()?;
+
+            Some(match node.kind() {
+                NodeKind::List | NodeKind::Vector | NodeKind::Map => {
+                    let (open, close) = delimiters(node);
+                    format!("{}{}{}", open, parts.join(" "), close)
+                }
+                _ => format!("{}{}", parts[0], parts[1..].join(" ")),
+            })
+        }
+    }
+}
+
+/// The text of the delimiters around a list, vector or map, as written.
+fn delimiters(node: &SyntaxNode) -> (String, String) {
+    let children = node.children();
+    let text = |child: Option<&SyntaxElement>| match child {
+        Some(SyntaxElement::Token(token)) => token.text().to_string(),
+        _ => String::new(),
+    };
+
+    (text(children.first()), text(children.last()))
+}
+
+/// How many arguments of a special form stay on the line of its head; the
+/// rest is a body indented by 2. `None` for ordinary calls.
+fn distinguished(head: &str, items: &[Item]) -> Option<usize> {
+    let datums = items.iter().filter(|item| !item.is_comment()).count();
+
+    match head {
+        // (define (f x) body) or (define x value), but (define f (x) body)
+        "define" => match items.get(1).map(|item| &item.element) {
+            Some(SyntaxElement::Node(_)) => Some(1),
+            _ if datums > 3 => Some(2),
+            _ => Some(1),
+        },
+        "defmacro" => Some(2),
+        "deftest" => Some(1),
+        "lambda" | "let" | "if" | "when" | "unless" | "define-macro" | "define-syntax"
+        | "syntax-rules" => Some(1),
+        "cond" | "begin" => Some(0),
+        _ => None,
+    }
+}
+
+struct Printer {
+    out: String,
+    width: usize,
+    /// The last thing written was a line comment, so nothing else can follow
+    /// on its line.
+    needs_newline: bool,
+}
+
+impl Printer {
+    fn column(&self) -> usize {
+        let line_start = self.out.rfind('\n').map_or(0, |newline| newline + 1);
+        self.out[line_start..].chars().count()
+    }
+
+    fn fits(&self, element: &SyntaxElement, column: usize) -> bool {
+        flat(element).is_some_and(|flat| column + flat.chars().count() <= self.width)
+    }
+
+    fn newline(&mut self, indent: usize) {
+        self.out.push('\n');
+        self.out.push_str(&" ".repeat(indent));
+        self.needs_newline = false;
+    }
+
+    fn write(&mut self, element: &SyntaxElement) {
+        if self.fits(element, self.column()) {
+            self.out.push_str(&flat(element).unwrap_or_default());
+            return;
+        }
+
+        match element {
+            SyntaxElement::Token(token) => {
+                self.out.push_str(token.text().trim_end());
+                self.needs_newline = token.text().starts_with(';');
+            }
+            SyntaxElement::Node(node)
+                if matches!(
+                    node.kind(),
+                    NodeKind::List | NodeKind::Vector | NodeKind::Map
+                ) =>
+            {
+                self.list(node)
+            }
+            // A quote shorthand or `#;` sticks to its datum
+            SyntaxElement::Node(node) => {
+                let items = items(node);
+                self.write(&items[0].element);
+                let column = self.column();
+                self.write_items(&items[1..], |datums| datums == 0, column);
+            }
+        }
+    }
+
+    /// Lays out a list, vector or map that does not fit on the line. Vectors
+    /// and maps hold data, so they never get the layout of a call, and each
+    /// value of a map stays on the line of its key.
+    fn list(&mut self, node: &SyntaxNode) {
+        let column = self.column();
+        let items = items(node);
+        let (open, close) = delimiters(node);
+        let head = match items.first().map(|item| &item.element) {
+            Some(SyntaxElement::Token(token))
+                if token.kind() != TokenType::COMMENT && node.kind() == NodeKind::List =>
+            {
+                Some(token.text().to_string())
+            }
+            _ => None,
+        };
+
+        let (same_line, indent) = match head {
+            Some(head) => match distinguished(&head, &items) {
+                Some(count) => (1 + count, column + 2),
+                None => {
+                    let aligned = column + 1 + head.chars().count() + 1;
+                    let args = items[1..].iter().filter(|item| !item.is_comment());
+                    let cramped = args.clone().any(|arg| !self.fits(&arg.element, aligned))
+                        && args.clone().all(|arg| self.fits(&arg.element, column + 2))
+                        && aligned > column + 2;
+
+                    match cramped {
+                        true => (1, column + 2),
+                        false => (2, aligned),
+                    }
+                }
+            },
+            None => (1, column + open.chars().count()),
+        };
+
+        self.out.push_str(&open);
+        match node.kind() {
+            NodeKind::Map => self.write_items(&items, |datums| datums % 2 == 1, indent),
+            _ => self.write_items(&items, |datums| datums < same_line, indent),
+        }
+        if self.needs_newline {
+            self.newline(indent);
+        }
+        self.out.push_str(&close);
+    }
+
+    /// Writes `items` in order: a datum goes on the current line, after a
+    /// space, when `same_line` holds for the number of data written before
+    /// it, and starts a new line at `indent` otherwise. Trailing comments
+    /// stay where they were.
+    fn write_items(&mut self, items: &[Item], same_line: impl Fn(usize) -> bool, indent: usize) {
+        let mut datums = 0;
+
+        for (index, item) in items.iter().enumerate() {
+            let comment = item.is_comment();
+
+            if index > 0 {
+                let stays = match comment {
+                    true => item.trailing,
+                    false => same_line(datums),
+                };
+
+                if stays && !self.needs_newline {
+                    self.out.push(' ');
+                } else {
+                    if item.blank_before {
+                        self.out.push('\n');
+                    }
+                    self.newline(indent);
+                }
+            }
+
+            self.write(&item.element);
+            if !comment {
+                datums += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str) -> String {
+        format_source(source, MAX_WIDTH).unwrap()
+    }
+
+    #[test]
+    fn short_forms_stay_on_one_line() {
+        assert_eq!(
+            format("(define   add (x y)\n     (+ x y))\n\n\n\n(add 1    2)"),
+            "(define add (x y) (+ x y))\n\n(add 1 2)\n"
+        );
+    }
+
+    #[test]
+    fn special_forms_indent_their_bodies() {
+        let source = "(define fib (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)) (fib (- n 3)) (fib (- n 4)))))";
+
+        assert_eq!(
+            format_source(source, 60),
+            Ok("(define fib (n)
+  (if (< n 2)
+    n
+    (+ (fib (- n 1))
+       (fib (- n 2))
+       (fib (- n 3))
+       (fib (- n 4)))))
+"
+            .to_string())
+        );
+        assert_eq!(
+            format_source(
+                "(let ((x 1) (y 2)) (display x) (cond ((< x y) y) (else x)))",
+                25
+            ),
+            Ok("(let ((x 1) (y 2))
+  (display x)
+  (cond
+    ((< x y) y)
+    (else x)))
+"
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn call_arguments_are_aligned() {
+        assert_eq!(
+            format_source("(string-append \"first part\" \"second part\" (f x))", 30),
+            Ok("(string-append \"first part\"
+               \"second part\"
+               (f x))
+"
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn vectors_keep_their_brackets_and_stay_data() {
+        assert_eq!(format("[1   2]  #( a\n b)"), "[1 2]\n#(a b)\n");
+        assert_eq!(
+            format_source("(define v [first-element second-element])", 30),
+            Ok("(define v
+  [first-element
+   second-element])
+"
+            .to_string())
+        );
+        assert_eq!(
+            format_source("#(if (f x) then-branch else-branch)", 20),
+            Ok("#(if
+  (f x)
+  then-branch
+  else-branch)
+"
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn map_entries_share_a_line() {
+        assert_eq!(format("{:a   1\n :b 2}"), "{:a 1 :b 2}\n");
+        assert_eq!(
+            format_source("(define m {:first (f x) :second second-value})", 25),
+            Ok("(define m
+  {:first (f x)
+   :second second-value})
+"
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn comments_are_preserved() {
+        let source = "; header\n\n(define x ; the answer\n  42)\n#| block |# (f #;(old) 1)\n(g a ; first\n b)";
+
+        assert_eq!(
+            format(source),
+            "; header
+
+(define x ; the answer
+  42)
+#| block |#
+(f #;(old) 1)
+(g a ; first
+   b)
+"
+        );
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let sources = [
+            "(define (fib n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)) (fib (- n 3)) (fib (- n 4)) (fib (- n 5)))))",
+            "(define fib (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)) (fib (- n 3)) (fib (- n 4)))))",
+            "(g a ; first\n b)",
+            "'(a b ; c\n d)",
+            "(f \"multi\nline\" x)",
+        ];
+
+        for source in sources {
+            let once = format(source);
+            assert_eq!(format(&once), once);
+            assert!(once.lines().all(|line| line.chars().count() <= MAX_WIDTH));
+        }
+    }
+
+    #[test]
+    fn refuses_malformed_source() {
+        assert!(format_source("(f (x)", MAX_WIDTH).is_err());
+        assert!(format_source(&"(".repeat(50_000), MAX_WIDTH).is_err());
+    }
+}
@@ -1,6 +1,17 @@
-use std::{collections::HashMap, vec};
-
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt,
+    rc::Rc,
+    sync::OnceLock,
+};
+
+use crate::builtins;
+use crate::lower::{Core, Expr, Lowerer};
 use crate::parser::*;
+use crate::printer;
+use crate::tokens::Position;
+use crate::value::{Closure, Value};
 
 #[derive(Clone, Debug)]
 pub enum Operation {
@@ -15,36 +26,37 @@ pub enum Operation {
     And,
     Or,
     Equ,
-    Neg,
     Not,
 }
 
-pub fn create_binary_map() -> HashMap<&'static str, Operation> {
-    let mut map = HashMap::new();
-    map.insert("+", Operation::Add);
-    map.insert("*", Operation::Mul);
-    map.insert("/", Operation::Div);
-    map.insert("-", Operation::Sub);
-    map
-}
-
-pub fn create_logic_map() -> HashMap<&'static str, Operation> {
-    let mut map = HashMap::new();
-    map.insert("<", Operation::Lt);
-    map.insert("<=", Operation::Lte);
-    map.insert(">", Operation::Gt);
-    map.insert(">=", Operation::Gte);
-    map.insert("&", Operation::And);
-    map.insert("|", Operation::Or);
-    map.insert("==", Operation::Equ);
-    map
+pub fn create_binary_map() -> &'static HashMap<&'static str, Operation> {
+    static MAP: OnceLock<HashMap<&'static str, Operation>> = OnceLock::new();
+
+    MAP.get_or_init(|| {
+        let mut map = HashMap::new();
+        map.insert("+", Operation::Add);
+        map.insert("*", Operation::Mul);
+        map.insert("/", Operation::Div);
+        map.insert("-", Operation::Sub);
+        map
+    })
 }
 
-pub fn create_unary_map() -> HashMap<&'static str, Operation> {
-    let mut map = HashMap::new();
-    map.insert("-", Operation::Neg);
-    map.insert("!", Operation::Not);
-    map
+pub fn create_logic_map() -> &'static HashMap<&'static str, Operation> {
+    static MAP: OnceLock<HashMap<&'static str, Operation>> = OnceLock::new();
+
+    MAP.get_or_init(|| {
+        let mut map = HashMap::new();
+        map.insert("<", Operation::Lt);
+        map.insert("<=", Operation::Lte);
+        map.insert(">", Operation::Gt);
+        map.insert(">=", Operation::Gte);
+        map.insert("&", Operation::And);
+        map.insert("|", Operation::Or);
+        map.insert("==", Operation::Equ);
+        map.insert("=", Operation::Equ);
+        map
+    })
 }
 
 #[derive(Debug, PartialEq)]
@@ -52,115 +64,266 @@ pub enum InterpretError {
     Expected(String),
     ParseError,
     IndexOutOfBounds,
+    Unbound(String),
+    /// The call depth reached the interpreter's recursion limit.
+    StackOverflow {
+        limit: usize,
+    },
+    /// Evaluation nested deeper than the thread's native stack budget allows.
+    OutOfStack,
+    /// An error raised inside function calls, with the calls it unwound
+    /// through, innermost first.
+    Traceback {
+        error: Box<InterpretError>,
+        frames: Vec<Frame>,
+    },
+}
+
+/// A function call active when an error was raised: the function and where
+/// the call to it was written.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Frame {
+    pub function: String,
+    pub call_site: Position,
 }
 
+/// Maximum depth of nested (non-tail) calls before `InterpretError::StackOverflow`.
+pub const DEFAULT_RECURSION_LIMIT: usize = 1000;
+
+/// Native stack that evaluation may use unless the thread says otherwise:
+/// half of the 2 MiB a spawned thread gets, leaving the rest to the caller.
+/// Unoptimised builds use several KiB per nested call, so this can run out
+/// before the recursion limit does.
+pub const DEFAULT_STACK_BUDGET: usize = 1024 * 1024;
+
+thread_local! {
+    /// Native stack that evaluation on this thread may use.
+    static STACK_BUDGET: Cell<usize> = const { Cell::new(DEFAULT_STACK_BUDGET) };
+    /// Stack address of the outermost `eval` running on this thread.
+    static STACK_START: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Lets evaluation on the current thread use up to `bytes` of native stack,
+/// for threads started with a larger stack than the default.
+pub fn set_stack_budget(bytes: usize) {
+    STACK_BUDGET.set(bytes);
+}
+
+/// Clears the stack start when the outermost `eval` on the thread returns.
+struct Outermost;
+
+impl Drop for Outermost {
+    fn drop(&mut self) {
+        STACK_START.set(None);
+    }
+}
+
+/// Evaluates top-level forms one at a time: each is lowered to `Core` and
+/// evaluated in the global `Environment`.
 #[derive(Debug)]
 pub struct Interpreter<'a> {
-    tokens: Vec<ParserResult>,
+    tokens: &'a [ParserResult],
     position: usize,
     result: String,
-    environment: &'a mut Environment,
+    environment: Environment,
+    lowerer: Lowerer,
+    depth: usize,
+    recursion_limit: usize,
 }
 
-#[derive(Clone, Debug)]
+/// A chain of scopes; cloning it shares the scopes rather than copying them.
+#[derive(Clone, Default)]
 pub struct Environment {
-    pub scopes: Vec<HashMap<String, Binding>>,
-    pub level: i32,
+    scope: Rc<RefCell<Scope>>,
 }
 
-pub trait Interpret {
-    fn interpret(&mut self) -> Result<(), InterpretError>;
-    fn interpret_expression(&mut self) -> Result<String, InterpretError>;
+#[derive(Default)]
+struct Scope {
+    bindings: HashMap<String, Value>,
+    parent: Option<Environment>,
 }
 
-#[derive(Clone, Debug)]
-pub struct Function {
-    params: Vec<String>,
-    body: Vec<ParserResult>,
-    closure: Environment,
+pub trait Interpret {
+    fn interpret(&mut self) -> Result<(), InterpretError>;
+    fn interpret_expression(&mut self) -> Result<Value, InterpretError>;
 }
 
-#[derive(Clone, Debug)]
-pub enum Binding {
-    Function(Function),
-    Value(String),
+/// Calls a procedure value with arguments that are already evaluated.
+pub trait Apply {
+    fn apply(&mut self, function: &Value, args: Vec<Value>) -> Result<Value, InterpretError>;
 }
 
-impl Function {
-    fn new(params: Vec<String>, body: Vec<ParserResult>, closure: Environment) -> Self {
-        Self {
-            params,
-            body,
-            closure,
+impl InterpretError {
+    fn with_frame(self, frame: Frame) -> Self {
+        match self {
+            InterpretError::Traceback { error, mut frames } => {
+                frames.push(frame);
+                InterpretError::Traceback { error, frames }
+            }
+            error => InterpretError::Traceback {
+                error: Box::new(error),
+                frames: vec![frame],
+            },
         }
     }
 
-    fn apply(
-        &self,
-        args: Vec<String>,
-        interpreter: &mut Interpreter,
-    ) -> Result<String, InterpretError> {
-        if args.len() != self.params.len() {
-            return Err(InterpretError::Expected(format!(
-                "Expected {} arguments, got {}",
-                self.params.len(),
-                args.len()
-            )));
+    /// The error as originally raised, without its traceback.
+    pub fn root(&self) -> &InterpretError {
+        match self {
+            InterpretError::Traceback { error, .. } => error.root(),
+            error => error,
         }
+    }
 
-        interpreter.begin_scope();
-
-        for (param, arg) in self.params.iter().zip(args.iter()) {
-            interpreter
-                .environment
-                .define(param.clone(), Binding::Value(arg.clone()));
+    /// Calls the error unwound through, innermost first.
+    pub fn frames(&self) -> &[Frame] {
+        match self {
+            InterpretError::Traceback { frames, .. } => frames,
+            _ => &[],
         }
+    }
+}
 
-        let mut body_interpreter = Interpreter::new(self.body.clone(), interpreter.environment);
-        let result = body_interpreter.interpret_expression();
+/// Number of identical consecutive frames printed before they are folded.
+const REPEATED_FRAMES_SHOWN: usize = 3;
+
+impl fmt::Display for InterpretError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterpretError::Expected(message) => write!(f, "{}", message),
+            InterpretError::ParseError => write!(f, "Could not parse operand"),
+            InterpretError::IndexOutOfBounds => write!(f, "Missing expression"),
+            InterpretError::Unbound(name) => write!(f, "Unbound variable: {}", name),
+            InterpretError::StackOverflow { limit } => {
+                write!(f, "Maximum recursion depth ({}) exceeded", limit)
+            }
+            InterpretError::OutOfStack => write!(f, "Nested too deeply for the stack"),
+            InterpretError::Traceback { error, frames } => {
+                writeln!(f, "Traceback (most recent call last):")?;
+
+                let mut repeated = 0;
+                let mut previous: Option<&Frame> = None;
+
+                for frame in frames.iter().rev() {
+                    if previous == Some(frame) {
+                        repeated += 1;
+                    } else {
+                        fold_repeats(f, repeated)?;
+                        repeated = 0;
+                    }
+                    if repeated < REPEATED_FRAMES_SHOWN {
+                        writeln!(f, "  at {}, in {}", frame.call_site, frame.function)?;
+                    }
+                    previous = Some(frame);
+                }
+                fold_repeats(f, repeated)?;
 
-        interpreter.end_scope();
+                write!(f, "Error: {}", error)
+            }
+        }
+    }
+}
 
-        result
+fn fold_repeats(f: &mut fmt::Formatter, repeated: usize) -> fmt::Result {
+    if repeated >= REPEATED_FRAMES_SHOWN {
+        writeln!(
+            f,
+            "  [Previous frame repeated {} more times]",
+            repeated + 1 - REPEATED_FRAMES_SHOWN
+        )?;
     }
+    Ok(())
 }
 
 impl Environment {
-    pub fn define(&mut self, name: String, function: Binding) {
-        if self.scopes.is_empty() {
-            self.scopes.push(HashMap::new());
+    /// The global environment, with the primitive procedures bound.
+    pub fn new() -> Self {
+        let environment = Self::default();
+        builtins::install(&environment);
+        environment
+    }
+
+    /// A new innermost scope whose lookups fall back to this environment.
+    pub fn extend(&self) -> Self {
+        Self {
+            scope: Rc::new(RefCell::new(Scope {
+                bindings: HashMap::new(),
+                parent: Some(self.clone()),
+            })),
         }
+    }
 
-        let current_scope = self.scopes.last_mut().unwrap();
-        current_scope.insert(name, function);
+    pub fn define(&self, name: String, value: Value) {
+        self.scope.borrow_mut().bindings.insert(name, value);
     }
 
-    pub fn lookup(&self, name: &str) -> Option<&Binding> {
-        for scope in self.scopes.iter().rev() {
-            if let Some(function) = scope.get(name) {
-                return Some(function);
+    /// Rebinds `name` in the innermost scope that already defines it.
+    pub fn set(&self, name: &str, value: Value) -> Result<(), InterpretError> {
+        let mut scope = self.scope.borrow_mut();
+
+        match scope.bindings.get_mut(name) {
+            Some(slot) => {
+                *slot = value;
+                Ok(())
             }
+            None => match &scope.parent {
+                Some(parent) => parent.set(name, value),
+                None => Err(InterpretError::Unbound(name.to_string())),
+            },
+        }
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<Value> {
+        let scope = self.scope.borrow();
+
+        match scope.bindings.get(name) {
+            Some(value) => Some(value.clone()),
+            None => scope.parent.as_ref()?.lookup(name),
         }
-        None
+    }
+}
+
+// Closures hold the environment they were created in, so printing the
+// bindings' values could recurse forever; list the names only.
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scope = self.scope.borrow();
+        let mut names: Vec<&String> = scope.bindings.keys().collect();
+        names.sort();
+
+        f.debug_struct("Environment")
+            .field("bindings", &names)
+            .field("parent", &scope.parent)
+            .finish()
     }
 }
 
 impl<'a> Interpreter<'a> {
-    pub fn new(tokens: Vec<ParserResult>, environment: &'a mut Environment) -> Self {
+    pub fn new(tokens: &'a [ParserResult], environment: &Environment) -> Self {
         Self {
             tokens,
             position: 0,
-            environment,
+            environment: environment.clone(),
             result: String::new(),
+            lowerer: Lowerer::new(),
+            depth: 0,
+            recursion_limit: DEFAULT_RECURSION_LIMIT,
         }
     }
 
+    /// Caps the depth of nested calls; deeper recursion fails with
+    /// `InterpretError::StackOverflow` instead of exhausting the native stack.
+    pub fn with_recursion_limit(mut self, limit: usize) -> Self {
+        self.recursion_limit = limit;
+        self
+    }
+
     pub fn get_result(&mut self) -> String {
         let _ = self.interpret();
         self.result.clone()
     }
 
-    fn current_token(&self) -> Option<&ParserResult> {
+    fn current_token(&self) -> Option<&'a ParserResult> {
         self.tokens.get(self.position)
     }
 
@@ -168,260 +331,234 @@ impl<'a> Interpreter<'a> {
         self.position += 1
     }
 
-    fn begin_scope(&mut self) {
-        self.environment.scopes.push(HashMap::new());
-        self.environment.level += 1;
+    /// Evaluates `expr`. Calls in tail position reuse this invocation instead
+    /// of nesting another one, so only non-tail calls count towards the
+    /// recursion limit; going past the thread's native stack budget fails with
+    /// `InterpretError::OutOfStack`. An error raised inside a call gets a frame naming the function
+    /// running at the time and the call that entered it.
+    pub fn eval(
+        &mut self,
+        expr: &Expr,
+        environment: &Environment,
+    ) -> Result<Value, InterpretError> {
+        let depth = self.depth;
+        let mut frame = None;
+
+        // How far the stack has grown, measured by where this call's locals are
+        let here = std::ptr::addr_of!(frame) as usize;
+        let _outermost = match STACK_START.get() {
+            None => {
+                STACK_START.set(Some(here));
+                Some(Outermost)
+            }
+            Some(start) if start.abs_diff(here) > STACK_BUDGET.get() => {
+                return Err(InterpretError::OutOfStack);
+            }
+            Some(_) => None,
+        };
+
+        let result = self.eval_in_frame(expr.clone(), environment.clone(), &mut frame);
+        self.depth = depth;
+
+        match (result, frame) {
+            (Err(error), Some(frame)) => Err(error.with_frame(frame)),
+            (result, _) => result,
+        }
     }
 
-    fn end_scope(&mut self) {
-        if !self.environment.scopes.is_empty() {
-            self.environment.scopes.pop();
-            self.environment.level -= 1;
+    fn eval_in_frame(
+        &mut self,
+        mut expr: Expr,
+        mut environment: Environment,
+        frame: &mut Option<Frame>,
+    ) -> Result<Value, InterpretError> {
+        let mut entered = false;
+
+        loop {
+            let next = match &*expr {
+                Core::Const(value) | Core::Quote(value) => return Ok(value.clone()),
+                Core::Literal(value) => return Ok(value.copy()),
+                Core::Var(name) => {
+                    return environment
+                        .lookup(name)
+                        .ok_or_else(|| InterpretError::Unbound(name.clone()))
+                }
+                Core::If(test, consequent, alternative) => {
+                    if self.eval(test, &environment)?.is_true() {
+                        consequent.clone()
+                    } else {
+                        alternative.clone()
+                    }
+                }
+                Core::Begin(body) => match body.split_last() {
+                    Some((last, init)) => {
+                        for form in init {
+                            self.eval(form, &environment)?;
+                        }
+                        last.clone()
+                    }
+                    None => return Ok(Value::Unspecified),
+                },
+                Core::Define(name, value) => {
+                    let value = self.eval(value, &environment)?;
+                    environment.define(name.clone(), value);
+                    return Ok(Value::Unspecified);
+                }
+                Core::Set(name, value) => {
+                    let value = self.eval(value, &environment)?;
+                    environment.set(name, value)?;
+                    return Ok(Value::Unspecified);
+                }
+                Core::Lambda(lambda) => {
+                    return Ok(Value::Procedure(Rc::new(Closure {
+                        lambda: lambda.clone(),
+                        environment: environment.clone(),
+                    })))
+                }
+                Core::Call {
+                    function,
+                    args,
+                    call_site,
+                } => {
+                    let function = self.eval(function, &environment)?;
+                    let args = args
+                        .iter()
+                        .map(|arg| self.eval(arg, &environment))
+                        .collect::<Result<Vec<_>, InterpretError>>()?;
+
+                    let closure = match function {
+                        Value::Procedure(closure) => closure,
+                        Value::Primitive(primitive) => return (primitive.function)(&args, self),
+                        other => {
+                            return Err(InterpretError::Expected(format!(
+                                "{} is not a procedure",
+                                other
+                            )))
+                        }
+                    };
+
+                    // A tail call replaces the caller, so it takes over the frame
+                    if let Some(call_site) = call_site {
+                        *frame = Some(Frame {
+                            function: closure.lambda.name.clone(),
+                            call_site: *call_site,
+                        });
+                    }
+
+                    if !entered {
+                        if self.depth >= self.recursion_limit {
+                            return Err(InterpretError::StackOverflow {
+                                limit: self.recursion_limit,
+                            });
+                        }
+                        self.depth += 1;
+                        entered = true;
+                    }
+
+                    environment = bind(&closure, args)?;
+                    closure.lambda.body.clone()
+                }
+            };
+
+            expr = next;
         }
     }
 }
 
+/// A scope for running `closure`'s body with its parameters bound to `args`.
+fn bind(closure: &Closure, args: Vec<Value>) -> Result<Environment, InterpretError> {
+    let lambda = &closure.lambda;
+
+    let arity_matches = match lambda.rest {
+        Some(_) => args.len() >= lambda.params.len(),
+        None => args.len() == lambda.params.len(),
+    };
+    if !arity_matches {
+        return Err(InterpretError::Expected(format!(
+            "Expected {}{} arguments, got {}",
+            if lambda.rest.is_some() {
+                "at least "
+            } else {
+                ""
+            },
+            lambda.params.len(),
+            args.len()
+        )));
+    }
+
+    let environment = closure.environment.extend();
+    let mut args = args.into_iter();
+
+    for (param, arg) in lambda.params.iter().zip(&mut args) {
+        environment.define(param.clone(), arg);
+    }
+    if let Some(rest) = &lambda.rest {
+        environment.define(rest.clone(), Value::List(args.collect()));
+    }
+
+    Ok(environment)
+}
+
+impl Apply for Interpreter<'_> {
+    /// Evaluates the call like one written in the program, so it counts
+    /// towards the recursion limit the same way.
+    fn apply(&mut self, function: &Value, args: Vec<Value>) -> Result<Value, InterpretError> {
+        let call = Rc::new(Core::Call {
+            function: Rc::new(Core::Const(function.clone())),
+            args: args
+                .into_iter()
+                .map(|arg| Rc::new(Core::Const(arg)))
+                .collect(),
+            call_site: None,
+        });
+        let environment = self.environment.clone();
+        self.eval(&call, &environment)
+    }
+}
+
 impl<'a> Interpret for Interpreter<'a> {
     fn interpret(&mut self) -> Result<(), InterpretError> {
         let mut results: Vec<String> = Vec::new();
 
         while self.position < self.tokens.len() {
             match self.interpret_expression() {
-                Ok(r) => {
-                    if !r.is_empty() {
-                        results.push(r);
-                    }
-                }
-                Err(e) => {
-                    println!("Error at position {}: {:?}", self.position, e);
-                    return Err(e);
-                }
+                Ok(Value::Unspecified) => {}
+                Ok(r) => results.push(printer::write(&r)),
+                Err(e) => return Err(e),
             }
         }
         self.result.push_str(&results.join(" "));
         Ok(())
     }
 
-    fn interpret_expression(&mut self) -> Result<String, InterpretError> {
-        match self.current_token() {
-            Some(token) => {
-                let token_clone = token.clone();
-                self.advance();
-
-                match token_clone {
-                    ParserResult::Atom(element) => match element.kind {
-                        Kind::Binary => {
-                            let operation = create_binary_map()
-                                .get(element.value.as_str())
-                                .ok_or_else(|| {
-                                    InterpretError::Expected(format!(
-                                        "Unknown operation: {}",
-                                        element.value
-                                    ))
-                                })?
-                                .clone();
-
-                            let left = self.interpret_expression()?;
-                            let right = self.interpret_expression();
-
-                            let left_val: f32 =
-                                left.parse().map_err(|_| InterpretError::ParseError)?;
-
-                            match right {
-                                Ok(r) => {
-                                    let right_val: f32 =
-                                        r.parse().map_err(|_| InterpretError::ParseError).unwrap();
-                                    Ok(binary(operation, left_val as f32, right_val as f32)
-                                        .to_string())
-                                }
-                                Err(_r) => Ok(unary(operation, left_val).to_string()),
-                            }
-                        }
-                        Kind::Unary => {
-                            let operand = self.interpret_expression()?;
-
-                            let operation = create_unary_map()
-                                .get(element.value.as_str())
-                                .ok_or_else(|| {
-                                    InterpretError::Expected(format!(
-                                        "Unknown operation: {}",
-                                        element.value
-                                    ))
-                                })?
-                                .clone();
-
-                            match operand.parse() {
-                                Ok(int) => Ok(unary(operation, int).to_string()),
-                                Err(_) => match parse_bool(&operand) {
-                                    Ok(b) => {
-                                        if b == false {
-                                            Ok("true".to_owned())
-                                        } else {
-                                            Ok("false".to_owned())
-                                        }
-                                    }
-                                    Err(_) => todo!(),
-                                },
-                            }
-                        }
-                        Kind::Identifier => match self.environment.lookup(&element.value) {
-                            Some(Binding::Function(function)) => {
-                                let function_clone = function.clone();
-                                let arity = function.params.len();
-
-                                if arity == 0 {
-                                    return function_clone.apply(vec![], self);
-                                }
-
-                                let mut params = Vec::with_capacity(arity);
-
-                                for _ in 0..arity {
-                                    let p = self.interpret_expression()?;
-                                    params.push(p);
-                                }
-
-                                function_clone.apply(params, self)
-                            }
-                            Some(Binding::Value(val)) => Ok(val.clone()),
-                            None => Ok(element.value),
-                        },
-                        Kind::Literal => Ok(element.value),
-                        Kind::Function => {
-                            let name = self.interpret_expression()?;
-
-                            // Parse Parameters
-                            let mut params = vec![];
-
-                            match self.current_token() {
-                                Some(ParserResult::Atom(e)) => params.push(e.value.clone()),
-                                Some(ParserResult::Expression(parser_results)) => {
-                                    for result in parser_results {
-                                        params.push(result.to_string());
-                                    }
-                                }
-                                None => {}
-                            }
-
-                            self.advance();
-
-                            // Parse Body
-                            let mut body: Vec<ParserResult> = vec![];
-                            match self.current_token() {
-                                Some(expression) => body.push(expression.clone()),
-                                None => {
-                                    return Err(InterpretError::Expected(
-                                        "Expected function body".to_string(),
-                                    ))
-                                }
-                            }
-
-                            self.advance();
-
-                            // Create Function
-                            let function = Function::new(params, body, self.environment.clone());
-                            self.environment.define(name, Binding::Function(function));
-
-                            Ok(String::new())
-                        }
-                        Kind::Condition => {
-                            let boolean = self.interpret_expression()?;
-
-                            match boolean.as_str() {
-                                "true" => {
-                                    let left_condition = self.interpret_expression()?; // Only evaluate then branch
-                                    self.advance();
-                                    Ok(left_condition)
-                                }
-                                "false" => {
-                                    self.advance();
-                                    let right_condition = self.interpret_expression()?; // Only evaluate else branch
-                                    Ok(right_condition)
-                                }
-                                _ => Err(InterpretError::IndexOutOfBounds),
-                            }
-                        }
-                        Kind::Format => todo!(),
-                        Kind::Comparison => {
-                            let operation = create_logic_map()
-                                .get(element.value.as_str())
-                                .ok_or_else(|| {
-                                    InterpretError::Expected(format!(
-                                        "Unknown operation: {}",
-                                        element.value
-                                    ))
-                                })?
-                                .clone();
-
-                            let left = self.interpret_expression()?;
-
-                            let right = self.interpret_expression()?;
-
-                            let left_val: f32 =
-                                left.parse().map_err(|_| InterpretError::ParseError)?;
-
-                            let right_val: f32 =
-                                right.parse().map_err(|_| InterpretError::ParseError)?;
+    fn interpret_expression(&mut self) -> Result<Value, InterpretError> {
+        let form = self
+            .current_token()
+            .ok_or(InterpretError::IndexOutOfBounds)?;
+        self.advance();
 
-                            let result = comparison(operation, left_val, right_val)?;
-
-                            Ok(result.to_string())
-                        }
-                        Kind::Bool => Ok(element.value),
-                        Kind::Logical => {
-                            let operation = create_logic_map()
-                                .get(element.value.as_str())
-                                .ok_or_else(|| {
-                                    InterpretError::Expected(format!(
-                                        "Unknown operation: {}",
-                                        element.value
-                                    ))
-                                })?
-                                .clone();
-
-                            let left = self.interpret_expression()?;
-
-                            let right = self.interpret_expression().expect(&format!(
-                                "{:?} operation expects two operands.",
-                                operation
-                            ));
-
-                            let left_val: bool = parse_bool(&left).expect("No operands found.");
-                            let right_val: bool = parse_bool(&right)
-                                .expect(&format!("{:?} operation expects two operands", operation));
-
-                            Ok(logical(operation, left_val, right_val)?.to_string())
-                        }
-                    },
-                    ParserResult::Expression(parser_results) => {
-                        let mut sub_interpreter =
-                            Interpreter::new(parser_results, self.environment);
-                        sub_interpreter.interpret_expression()
-                    }
-                }
-            }
-            None => Err(InterpretError::IndexOutOfBounds),
-        }
+        let expr = self.lowerer.lower(form)?;
+        let environment = self.environment.clone();
+        self.eval(&expr, &environment)
     }
 }
 
 // Helper Functions
 
-fn binary(operation: Operation, left: f32, right: f32) -> f32 {
+pub fn binary(operation: Operation, left: f32, right: f32) -> Result<f32, InterpretError> {
     match operation {
-        Operation::Add => left + right,
-        Operation::Mul => left * right,
-        Operation::Div => {
-            if right == 0.0 {
-                panic!("Division by zero");
-            }
-            left / right
+        Operation::Add => Ok(left + right),
+        Operation::Mul => Ok(left * right),
+        Operation::Div if right == 0.0 => {
+            Err(InterpretError::Expected("Division by zero".to_string()))
         }
-        Operation::Sub => left - right,
-        _ => 0.0,
+        Operation::Div => Ok(left / right),
+        Operation::Sub => Ok(left - right),
+        _ => Ok(0.0),
     }
 }
 
-fn comparison(operation: Operation, left: f32, right: f32) -> Result<bool, InterpretError> {
+pub fn comparison(operation: Operation, left: f32, right: f32) -> Result<bool, InterpretError> {
     match operation {
         Operation::Lt => Ok(left < right),
         Operation::Lte => Ok(left <= right),
@@ -435,7 +572,7 @@ fn comparison(operation: Operation, left: f32, right: f32) -> Result<bool, Inter
     }
 }
 
-fn logical(operation: Operation, left: bool, right: bool) -> Result<bool, InterpretError> {
+pub fn logical(operation: Operation, left: bool, right: bool) -> Result<bool, InterpretError> {
     match operation {
         Operation::And => Ok(left && right),
         Operation::Or => Ok(left || right),
@@ -446,7 +583,7 @@ fn logical(operation: Operation, left: bool, right: bool) -> Result<bool, Interp
     }
 }
 
-fn unary(operation: Operation, left: f32) -> f32 {
+pub fn unary(operation: Operation, left: f32) -> f32 {
     match operation {
         Operation::Sub => -left,
         Operation::Not => match left {
@@ -458,10 +595,216 @@ fn unary(operation: Operation, left: f32) -> f32 {
     }
 }
 
-fn parse_bool(b: &str) -> Result<bool, String> {
-    match b {
-        "true" => Ok(true),
-        "false" => Ok(false),
-        _ => Err("Error converting to boolean".to_string()),
+/// Expands and runs `source` in a fresh environment, and returns what its
+/// top-level forms evaluated to, the way the unit tests check programs.
+#[cfg(test)]
+pub(crate) fn run(source: &str) -> Result<String, InterpretError> {
+    run_with_limit(source, DEFAULT_RECURSION_LIMIT)
+}
+
+#[cfg(test)]
+pub(crate) fn run_with_limit(source: &str, limit: usize) -> Result<String, InterpretError> {
+    let program = crate::macros::Expander::new().expand_program(&read(source).unwrap())?;
+
+    let env = Environment::new();
+    let mut interpreter = Interpreter::new(&program, &env).with_recursion_limit(limit);
+    interpreter.interpret()?;
+    Ok(interpreter.result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEPTH: &str = "(define depth (n) (if (<= n 0) 0 (+ 1 (depth (- n 1)))))";
+
+    #[test]
+    fn tail_recursive_if_runs_in_constant_stack() {
+        let source = "
+            (define countdown (n)
+                (if (<= n 0)
+                    0
+                    (countdown (- n 1))))
+            (countdown 100000)";
+
+        assert_eq!(run(source), Ok("0".to_string()));
+    }
+
+    #[test]
+    fn tail_calls_through_cond_begin_and_let() {
+        let source = "
+            (define walk (n)
+                (cond ((<= n 0) 42)
+                      (else (begin
+                                (let ((m (- n 1)))
+                                    (walk m))))))
+            (walk 100000)";
+
+        assert_eq!(run(source), Ok("42".to_string()));
+    }
+
+    #[test]
+    fn mutual_tail_calls() {
+        let source = "
+            (define ping (n) (if (<= n 0) 1 (pong (- n 1))))
+            (define pong (n) (if (<= n 0) 0 (ping (- n 1))))
+            (ping 100001)";
+
+        assert_eq!(run(source), Ok("0".to_string()));
+    }
+
+    #[test]
+    fn non_tail_calls_still_return_values() {
+        let source = "
+            (define fact (n) (if (<= n 1) 1 (* n (fact (- n 1)))))
+            (fact 5)";
+
+        assert_eq!(run(source), Ok("120".to_string()));
+    }
+
+    #[test]
+    fn recursion_below_the_limit_succeeds() {
+        let source = format!("{} (depth 40)", DEPTH);
+
+        assert_eq!(run_with_limit(&source, 50), Ok("40".to_string()));
+    }
+
+    #[test]
+    fn deep_recursion_raises_stack_overflow() {
+        let source = format!("{} (depth 100)", DEPTH);
+
+        let error = run_with_limit(&source, 50).unwrap_err();
+
+        assert_eq!(error.root(), &InterpretError::StackOverflow { limit: 50 });
+        assert_eq!(error.frames().len(), 51);
+        assert!(error.frames().iter().all(|frame| frame.function == "depth"));
+
+        let traceback = error.to_string();
+        assert!(traceback.contains("at 1:59, in depth"));
+        assert!(traceback.contains("[Previous frame repeated 47 more times]"));
+        assert!(traceback.ends_with("Error: Maximum recursion depth (50) exceeded"));
+    }
+
+    #[test]
+    fn deep_recursion_fails_before_the_native_stack() {
+        let sources = [
+            format!("{} (depth 100000)", DEPTH),
+            "(define deep (n) (+ 1 (+ 1 (+ 1 (+ 1 (deep n)))))) (deep 0)".to_string(),
+            "(define down (n) (vector-map down (vector n))) (down 0)".to_string(),
+        ];
+
+        // A default thread, with the default stack size and stack budget
+        for source in sources {
+            let result = std::thread::spawn(move || run(&source)).join().unwrap();
+            assert!(matches!(
+                result.unwrap_err().root(),
+                InterpretError::OutOfStack | InterpretError::StackOverflow { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn a_larger_stack_budget_reaches_the_recursion_limit() {
+        let source = format!("{} (depth 100000)", DEPTH);
+
+        let result = std::thread::Builder::new()
+            .stack_size(64 * 1024 * 1024)
+            .spawn(move || {
+                set_stack_budget(32 * 1024 * 1024);
+                run(&source)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(
+            result.unwrap_err().root(),
+            &InterpretError::StackOverflow {
+                limit: DEFAULT_RECURSION_LIMIT
+            }
+        );
+    }
+
+    #[test]
+    fn tail_calls_do_not_count_towards_the_limit() {
+        let source = "
+            (define countdown (n) (if (<= n 0) 0 (countdown (- n 1))))
+            (countdown 1000)";
+
+        assert_eq!(run_with_limit(source, 10), Ok("0".to_string()));
+    }
+
+    #[test]
+    fn errors_carry_the_calls_they_unwound_through() {
+        let source = "
+            (define inner (x) (set! missing x))
+            (define outer (x) (+ 1 (inner x)))
+            (outer 5)";
+
+        let error = run(source).unwrap_err();
+
+        assert_eq!(
+            error.root(),
+            &InterpretError::Unbound("missing".to_string())
+        );
+        assert_eq!(
+            error.frames(),
+            &[
+                Frame {
+                    function: "inner".to_string(),
+                    call_site: Position {
+                        line: 3,
+                        column: 37
+                    },
+                },
+                Frame {
+                    function: "outer".to_string(),
+                    call_site: Position {
+                        line: 4,
+                        column: 14
+                    },
+                },
+            ]
+        );
+        assert_eq!(
+            error.to_string(),
+            "Traceback (most recent call last):\n  at 4:14, in outer\n  at 3:37, in inner\nError: Unbound variable: missing"
+        );
+    }
+
+    #[test]
+    fn tail_calls_take_over_the_frame_of_their_caller() {
+        let source = "
+            (define inner (x) (set! missing x))
+            (define middle (x) (inner x))
+            (define outer (x) (+ 1 (middle x)))
+            (outer 5)";
+
+        assert_eq!(
+            run(source).unwrap_err().to_string(),
+            "Traceback (most recent call last):\n  at 5:14, in outer\n  at 3:33, in inner\nError: Unbound variable: missing"
+        );
+    }
+
+    #[test]
+    fn top_level_errors_have_no_frames() {
+        let error = run("(set! missing 1)").unwrap_err();
+
+        assert!(error.frames().is_empty());
+        assert_eq!(error.to_string(), "Unbound variable: missing");
+    }
+
+    #[test]
+    fn operators_are_procedures_like_any_other() {
+        let source = "
+            (define plus +)
+            (define zero? (n) (= n 0))
+            (define *scale* 3)
+            (define apply-op (op a b) (op a b))
+            (plus 1 2)
+            (apply-op * *scale* 4)
+            (zero? (- 2 2))";
+
+        assert_eq!(run(source), Ok("3 12 true".to_string()));
     }
 }
@@ -0,0 +1,18 @@
+pub mod builtins;
+pub mod format;
+pub mod formatter;
+pub mod interpreter;
+pub mod lower;
+pub mod macros;
+pub mod map;
+pub mod parser;
+pub mod printer;
+pub mod symbol;
+pub mod syntax;
+pub mod syntax_rules;
+pub mod testing;
+pub mod tokenizer;
+pub mod tokens;
+pub mod value;
+
+pub use parser::ParserResult;
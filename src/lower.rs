@@ -0,0 +1,631 @@
+use std::rc::Rc;
+
+use crate::builtins::derived::{self, applying, primitive};
+use crate::interpreter::InterpretError;
+use crate::parser::*;
+use crate::tokenizer::parse_number;
+use crate::tokens::Position;
+use crate::value::Value;
+
+pub type Expr = Rc<Core>;
+
+/// The core language the `Interpreter` evaluates. Every derived form (`let`,
+/// `cond`, `when`, `unless`, `and`, `or`) is rewritten into these by `Lowerer`.
+#[derive(Debug)]
+pub enum Core {
+    Const(Value),
+    Var(String),
+    If(Expr, Expr, Expr),
+    Lambda(Rc<Lambda>),
+    /// `call_site` is where the call was written, for tracebacks; calls
+    /// introduced by desugaring have none.
+    Call {
+        function: Expr,
+        args: Vec<Expr>,
+        call_site: Option<Position>,
+    },
+    Define(String, Expr),
+    Set(String, Expr),
+    Begin(Vec<Expr>),
+    Quote(Value),
+    /// A vector or map literal. Each evaluation makes a fresh copy, so
+    /// changing what one returned doesn't change the literal.
+    Literal(Value),
+}
+
+#[derive(Debug)]
+pub struct Lambda {
+    pub name: String,
+    pub params: Vec<String>,
+    /// Parameter bound to the list of extra arguments: `(lambda (a . rest) ...)`.
+    pub rest: Option<String>,
+    pub body: Expr,
+}
+
+/// Lowers parsed (and macro-expanded) forms into `Core`.
+#[derive(Debug, Default)]
+pub struct Lowerer;
+
+impl Lowerer {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn lower(&mut self, form: &ParserResult) -> Result<Expr, InterpretError> {
+        let items = match form {
+            ParserResult::Atom(element) => return Ok(lower_atom(element)),
+            ParserResult::Expression(items) => items,
+            ParserResult::Vector(_) | ParserResult::Map(_) => {
+                return Ok(Rc::new(Core::Literal(Value::from_datum(form))))
+            }
+        };
+
+        let head = match items.first() {
+            Some(ParserResult::Atom(head)) => head,
+            Some(_) => return self.lower_call(form, items),
+            None => return Err(InterpretError::IndexOutOfBounds),
+        };
+
+        match head.kind {
+            Kind::Function => self.lower_define(items),
+            Kind::Lambda => match items.get(1) {
+                Some(params) if items.len() > 2 => self.lower_lambda("lambda", params, &items[2..]),
+                _ => Err(InterpretError::Expected(
+                    "Expected (lambda (params...) body...)".to_string(),
+                )),
+            },
+            Kind::Condition => {
+                if items.len() != 3 && items.len() != 4 {
+                    return Err(InterpretError::Expected(
+                        "Expected (if test then [else])".to_string(),
+                    ));
+                }
+                let alternative = match items.get(3) {
+                    Some(alternative) => self.lower(alternative)?,
+                    None => unspecified(),
+                };
+                Ok(Rc::new(Core::If(
+                    self.lower(&items[1])?,
+                    self.lower(&items[2])?,
+                    alternative,
+                )))
+            }
+            Kind::Set => match items.as_slice() {
+                [_, name, value] => Ok(Rc::new(Core::Set(expect_name(name)?, self.lower(value)?))),
+                _ => Err(InterpretError::Expected(
+                    "Expected (set! name value)".to_string(),
+                )),
+            },
+            Kind::Begin => self.lower_body(&items[1..]),
+            Kind::Quote => match items.as_slice() {
+                [_, datum] => Ok(Rc::new(Core::Quote(Value::from_datum(datum)))),
+                _ => Err(InterpretError::Expected(
+                    "Expected an expression to quote".to_string(),
+                )),
+            },
+            Kind::Quasiquote => match items.as_slice() {
+                [_, template] => self.lower_quasiquote(template, 1),
+                _ => Err(InterpretError::Expected(
+                    "Expected an expression to quasiquote".to_string(),
+                )),
+            },
+            Kind::Let => self.lower_let(items),
+            Kind::Cond => self.lower_cond(&items[1..]),
+            Kind::When | Kind::Unless => {
+                if items.len() < 3 {
+                    return Err(InterpretError::Expected(format!(
+                        "Expected ({} test body...)",
+                        head.value
+                    )));
+                }
+                let test = self.lower(&items[1])?;
+                let body = self.lower_body(&items[2..])?;
+                Ok(Rc::new(if head.kind == Kind::When {
+                    Core::If(test, body, unspecified())
+                } else {
+                    Core::If(test, unspecified(), body)
+                }))
+            }
+            Kind::And => self.lower_and(&items[1..]),
+            Kind::Or => self.lower_or(&items[1..]),
+            // Tests only run under `ilisp test`; elsewhere they are skipped
+            Kind::Deftest => match items.get(1) {
+                Some(name) => expect_name(name).map(|_| unspecified()),
+                None => Err(InterpretError::Expected(
+                    "Expected (deftest name body...)".to_string(),
+                )),
+            },
+            // A call to a primitive that expects calling the thunk to fail
+            Kind::AssertError => match items.as_slice() {
+                [_, expr] => {
+                    let thunk = Lambda {
+                        name: "assert-error".to_string(),
+                        params: vec![],
+                        rest: None,
+                        body: self.lower(expr)?,
+                    };
+                    Ok(call(
+                        applying("assert-error", Box::new(derived::assert_error)),
+                        vec![Rc::new(Core::Lambda(Rc::new(thunk)))],
+                    ))
+                }
+                _ => Err(InterpretError::Expected(
+                    "Expected (assert-error expr)".to_string(),
+                )),
+            },
+            _ => self.lower_call(form, items),
+        }
+    }
+
+    /// `` `template ``: the template as data, except that `,expr` stands for
+    /// the value of `expr` and `,@expr` for the elements of the list it gives.
+    /// Only unquotes at `depth` 1 are evaluated; nested quasiquotes go deeper.
+    fn lower_quasiquote(
+        &mut self,
+        template: &ParserResult,
+        depth: usize,
+    ) -> Result<Expr, InterpretError> {
+        if !unquotes(template, depth) {
+            return Ok(Rc::new(match template {
+                ParserResult::Vector(_) | ParserResult::Map(_) => {
+                    Core::Literal(Value::from_datum(template))
+                }
+                _ => Core::Quote(Value::from_datum(template)),
+            }));
+        }
+
+        let (items, inner) = match template {
+            ParserResult::Expression(items) => match unquote(items) {
+                Some(_) if depth == 1 => return self.lower(&items[1]),
+                _ => (items, inner_depth(items, depth)),
+            },
+            ParserResult::Vector(items) => {
+                let list =
+                    self.lower_quasiquote(&ParserResult::Expression(items.clone()), depth)?;
+                return Ok(call(
+                    primitive("list->vector", derived::list_to_vector),
+                    vec![list],
+                ));
+            }
+            ParserResult::Map(items) => {
+                let list =
+                    self.lower_quasiquote(&ParserResult::Expression(items.clone()), depth)?;
+                return Ok(call(primitive("hash", derived::list_to_hash), vec![list]));
+            }
+            ParserResult::Atom(_) => unreachable!("atoms hold no unquotes"),
+        };
+
+        // Each element is a list to append: one item long, or spliced in
+        let mut segments = vec![];
+        for (index, item) in items.iter().enumerate() {
+            let depth = if index == 0 { depth } else { inner };
+            segments.push(match item {
+                ParserResult::Expression(splice)
+                    if depth == 1 && unquote(splice) == Some("unquote-splicing") =>
+                {
+                    self.lower(&splice[1])?
+                }
+                _ => call(
+                    primitive("list", derived::list),
+                    vec![self.lower_quasiquote(item, depth)?],
+                ),
+            });
+        }
+
+        Ok(call(primitive("append", derived::append), segments))
+    }
+
+    fn lower_call(
+        &mut self,
+        form: &ParserResult,
+        items: &[ParserResult],
+    ) -> Result<Expr, InterpretError> {
+        Ok(Rc::new(Core::Call {
+            function: self.lower(&items[0])?,
+            args: self.lower_all(&items[1..])?,
+            call_site: form.position(),
+        }))
+    }
+
+    fn lower_all(&mut self, forms: &[ParserResult]) -> Result<Vec<Expr>, InterpretError> {
+        forms.iter().map(|form| self.lower(form)).collect()
+    }
+
+    /// A sequence of forms, as in a `begin` or a function body.
+    fn lower_body(&mut self, forms: &[ParserResult]) -> Result<Expr, InterpretError> {
+        let mut body = self.lower_all(forms)?;
+
+        Ok(match body.len() {
+            0 => unspecified(),
+            1 => body.remove(0),
+            _ => Rc::new(Core::Begin(body)),
+        })
+    }
+
+    /// `(define name value)` or `(define name (params...) body...)`; a single
+    /// parameter may also be written bare: `(define name param body...)`.
+    fn lower_define(&mut self, items: &[ParserResult]) -> Result<Expr, InterpretError> {
+        let name = match items.get(1) {
+            Some(name) => expect_name(name)?,
+            None => return Err(InterpretError::Expected("Expected a name".to_string())),
+        };
+
+        let value = match items.len() {
+            3 => match &items[2] {
+                ParserResult::Expression(lambda)
+                    if lambda.len() > 2 && head_kind(lambda) == Some(&Kind::Lambda) =>
+                {
+                    self.lower_lambda(&name, &lambda[1], &lambda[2..])?
+                }
+                value => self.lower(value)?,
+            },
+            _ if items.len() > 3 => match &items[2] {
+                ParserResult::Atom(param) => {
+                    let params = ParserResult::Expression(vec![ParserResult::Atom(param.clone())]);
+                    self.lower_lambda(&name, &params, &items[3..])?
+                }
+                params => self.lower_lambda(&name, params, &items[3..])?,
+            },
+            _ => {
+                return Err(InterpretError::Expected(
+                    "Expected function body".to_string(),
+                ))
+            }
+        };
+
+        Ok(Rc::new(Core::Define(name, value)))
+    }
+
+    fn lower_lambda(
+        &mut self,
+        name: &str,
+        params: &ParserResult,
+        body: &[ParserResult],
+    ) -> Result<Expr, InterpretError> {
+        let (params, rest) = match params {
+            ParserResult::Expression(items) => match items.iter().position(is_dot) {
+                Some(dot) if dot + 2 == items.len() => {
+                    (names(&items[..dot])?, Some(expect_name(&items[dot + 1])?))
+                }
+                Some(_) => {
+                    return Err(InterpretError::Expected(
+                        "Expected a single parameter after .".to_string(),
+                    ))
+                }
+                None => (names(items)?, None),
+            },
+            // (lambda args body...) takes every argument as a list
+            _ => (vec![], Some(expect_name(params)?)),
+        };
+
+        Ok(Rc::new(Core::Lambda(Rc::new(Lambda {
+            name: name.to_string(),
+            params,
+            rest,
+            body: self.lower_body(body)?,
+        }))))
+    }
+
+    /// `(let ((name value)...) body...)` becomes `((lambda (name...) body...) value...)`.
+    fn lower_let(&mut self, items: &[ParserResult]) -> Result<Expr, InterpretError> {
+        let bindings = match items.get(1) {
+            Some(ParserResult::Expression(bindings)) => bindings,
+            _ => {
+                return Err(InterpretError::Expected(
+                    "Expected let bindings".to_string(),
+                ))
+            }
+        };
+
+        let mut params = Vec::with_capacity(bindings.len());
+        let mut args = Vec::with_capacity(bindings.len());
+
+        for binding in bindings {
+            match binding {
+                ParserResult::Expression(pair) if pair.len() == 2 => {
+                    params.push(expect_name(&pair[0])?);
+                    args.push(self.lower(&pair[1])?);
+                }
+                _ => {
+                    return Err(InterpretError::Expected(
+                        "Expected (name value) binding".to_string(),
+                    ))
+                }
+            }
+        }
+
+        let lambda = Lambda {
+            name: "let".to_string(),
+            params,
+            rest: None,
+            body: self.lower_body(&items[2..])?,
+        };
+
+        Ok(derived_call(Rc::new(Core::Lambda(Rc::new(lambda))), args))
+    }
+
+    /// `(cond (test body...)... (else body...))` becomes nested `if`s. A clause
+    /// without a body yields the value of its test.
+    fn lower_cond(&mut self, clauses: &[ParserResult]) -> Result<Expr, InterpretError> {
+        let (clause, rest) = match clauses.split_first() {
+            Some((ParserResult::Expression(clause), rest)) if !clause.is_empty() => (clause, rest),
+            Some(_) => {
+                return Err(InterpretError::Expected(
+                    "Expected (test body...) cond clause".to_string(),
+                ))
+            }
+            None => return Ok(unspecified()),
+        };
+
+        if matches!(&clause[0], ParserResult::Atom(e) if e.value == "else") {
+            return self.lower_body(&clause[1..]);
+        }
+
+        if clause.len() == 1 {
+            let test = self.lower(&clause[0])?;
+            let rest = self.lower_cond(rest)?;
+            return Ok(or_else(test, rest));
+        }
+
+        Ok(Rc::new(Core::If(
+            self.lower(&clause[0])?,
+            self.lower_body(&clause[1..])?,
+            self.lower_cond(rest)?,
+        )))
+    }
+
+    fn lower_and(&mut self, forms: &[ParserResult]) -> Result<Expr, InterpretError> {
+        match forms {
+            [] => Ok(Rc::new(Core::Const(Value::Bool(true)))),
+            [last] => self.lower(last),
+            [first, rest @ ..] => Ok(Rc::new(Core::If(
+                self.lower(first)?,
+                self.lower_and(rest)?,
+                Rc::new(Core::Const(Value::Bool(false))),
+            ))),
+        }
+    }
+
+    fn lower_or(&mut self, forms: &[ParserResult]) -> Result<Expr, InterpretError> {
+        match forms {
+            [] => Ok(Rc::new(Core::Const(Value::Bool(false)))),
+            [last] => self.lower(last),
+            [first, rest @ ..] => {
+                let first = self.lower(first)?;
+                let rest = self.lower_or(rest)?;
+                Ok(or_else(first, rest))
+            }
+        }
+    }
+}
+
+/// The value of `test` if it is true, otherwise the value of `alternative`,
+/// evaluating `test` once. `alternative` is wrapped in a thunk made outside
+/// the binding that holds `test`, so no name in it can be captured:
+/// `((lambda (t k) (if t t (k))) test (lambda () alternative))`.
+fn or_else(test: Expr, alternative: Expr) -> Expr {
+    let value = Rc::new(Core::Var("t".to_string()));
+    let otherwise = Rc::new(Core::Var("k".to_string()));
+
+    let choose = Lambda {
+        name: "or".to_string(),
+        params: vec!["t".to_string(), "k".to_string()],
+        rest: None,
+        body: Rc::new(Core::If(
+            value.clone(),
+            value,
+            derived_call(otherwise, vec![]),
+        )),
+    };
+    let thunk = Lambda {
+        name: "or".to_string(),
+        params: vec![],
+        rest: None,
+        body: alternative,
+    };
+
+    derived_call(
+        Rc::new(Core::Lambda(Rc::new(choose))),
+        vec![test, Rc::new(Core::Lambda(Rc::new(thunk)))],
+    )
+}
+
+fn lower_atom(element: &Element) -> Expr {
+    Rc::new(match element.kind {
+        Kind::Bool | Kind::Literal | Kind::String | Kind::Keyword => {
+            Core::Const(Value::from_datum(&ParserResult::Atom(element.clone())))
+        }
+        _ => match parse_number(&element.value) {
+            Some(number) => Core::Const(Value::Number(number)),
+            None => Core::Var(element.value.clone()),
+        },
+    })
+}
+
+fn derived_call(function: Expr, args: Vec<Expr>) -> Expr {
+    Rc::new(Core::Call {
+        function,
+        args,
+        call_site: None,
+    })
+}
+
+fn call(function: Value, args: Vec<Expr>) -> Expr {
+    derived_call(Rc::new(Core::Const(function)), args)
+}
+
+/// `unquote` or `unquote-splicing` if `items` is one of them.
+fn unquote(items: &[ParserResult]) -> Option<&str> {
+    match items {
+        [ParserResult::Atom(head), _]
+            if head.value == "unquote" || head.value == "unquote-splicing" =>
+        {
+            Some(&head.value)
+        }
+        _ => None,
+    }
+}
+
+/// The quasiquote depth of the elements after the head of `items`.
+fn inner_depth(items: &[ParserResult], depth: usize) -> usize {
+    match (unquote(items), head_kind(items)) {
+        (Some(_), _) => depth - 1,
+        (None, Some(Kind::Quasiquote)) if items.len() == 2 => depth + 1,
+        _ => depth,
+    }
+}
+
+/// Whether a quasiquoted `template` has anything to evaluate.
+fn unquotes(template: &ParserResult, depth: usize) -> bool {
+    match template {
+        ParserResult::Atom(_) => false,
+        ParserResult::Expression(items) if unquote(items).is_some() && depth == 1 => true,
+        ParserResult::Expression(items) => {
+            let inner = inner_depth(items, depth);
+            items
+                .iter()
+                .enumerate()
+                .any(|(index, item)| unquotes(item, if index == 0 { depth } else { inner }))
+        }
+        ParserResult::Vector(items) | ParserResult::Map(items) => {
+            items.iter().any(|item| unquotes(item, depth))
+        }
+    }
+}
+
+fn unspecified() -> Expr {
+    Rc::new(Core::Const(Value::Unspecified))
+}
+
+fn head_kind(items: &[ParserResult]) -> Option<&Kind> {
+    match items.first() {
+        Some(ParserResult::Atom(head)) => Some(&head.kind),
+        _ => None,
+    }
+}
+
+fn is_dot(form: &ParserResult) -> bool {
+    matches!(form, ParserResult::Atom(element) if element.value == ".")
+}
+
+fn expect_name(form: &ParserResult) -> Result<String, InterpretError> {
+    match form {
+        ParserResult::Atom(element) if element.kind == Kind::Identifier => {
+            Ok(element.value.clone())
+        }
+        _ => Err(InterpretError::Expected(format!(
+            "Expected a name, got {}",
+            form.to_source()
+        ))),
+    }
+}
+
+fn names(forms: &[ParserResult]) -> Result<Vec<String>, InterpretError> {
+    forms.iter().map(expect_name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::run;
+
+    #[test]
+    fn let_becomes_a_call_to_a_lambda() {
+        let expr = Lowerer::new()
+            .lower(&read("(let ((x 1)) x)").unwrap()[0])
+            .unwrap();
+
+        match &*expr {
+            Core::Call {
+                function,
+                args,
+                call_site: None,
+            } => {
+                assert!(matches!(&**function, Core::Lambda(lambda) if lambda.params == ["x"]));
+                assert_eq!(args.len(), 1);
+            }
+            other => panic!("Expected a call, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn derived_forms() {
+        assert_eq!(
+            run("(and 1 2) (and true false 3) (and)"),
+            Ok("2 false true".to_string())
+        );
+        assert_eq!(
+            run("(or false 7) (or false false) (or)"),
+            Ok("7 false false".to_string())
+        );
+        assert_eq!(
+            run("(when (< 1 2) 1 2) (unless true 1)"),
+            Ok("2".to_string())
+        );
+        assert_eq!(run("(cond (false 1) (5) (else 2))"), Ok("5".to_string()));
+        assert_eq!(
+            run("(define t 1) (define k 2) (define |#:t1| 3) (or false t) (or false k) (or false |#:t1|)"),
+            Ok("1 2 3".to_string())
+        );
+    }
+
+    #[test]
+    fn quasiquote_builds_data_at_runtime() {
+        let source = "
+            (define x 5)
+            (define xs (list 1 2))
+            `(a ,x ,@xs end)
+            `(nested (,x) ,@(append xs xs))
+            `#(v ,x ,@xs)
+            `{:x ,x}
+            `(literal ,@'())
+            `(outer `(inner ,(still ,x)))
+            (define list 'shadowed)
+            `(,x)";
+
+        assert_eq!(
+            run(source),
+            Ok(
+                "(a 5 1 2 end) (nested (5) 1 2 1 2) #(v 5 1 2) {:x 5} (literal) \
+                (outer (quasiquote (inner (unquote (still 5))))) (5)"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            run("`(a ,@5)"),
+            Err(InterpretError::Expected(
+                "Expected a list, got 5".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn or_evaluates_each_test_once() {
+        let source = "
+            (define n 0)
+            (or (begin (set! n (+ n 1)) n) 5)
+            n";
+
+        assert_eq!(run(source), Ok("1 1".to_string()));
+    }
+
+    #[test]
+    fn lambdas_close_over_their_environment() {
+        let source = "
+            (define make-adder (n) (lambda (x) (+ x n)))
+            (define add2 (make-adder 2))
+            (define n 100)
+            (add2 5)";
+
+        assert_eq!(run(source), Ok("7".to_string()));
+    }
+
+    #[test]
+    fn rest_parameters_collect_extra_arguments() {
+        let source = "
+            ((lambda (a . rest) rest) 1 2 3)
+            ((lambda args args))";
+
+        assert_eq!(run(source), Ok("(2 3) ()".to_string()));
+    }
+}
@@ -0,0 +1,465 @@
+use std::{collections::HashMap, slice};
+
+use crate::interpreter::{Apply, Environment, Interpret, InterpretError, Interpreter};
+use crate::parser::*;
+use crate::symbol::Symbol;
+use crate::syntax_rules::SyntaxRules;
+use crate::value::Value;
+
+/// A `defmacro` transformer: a procedure from the unevaluated argument forms,
+/// as quoted data, to the data of the replacement code.
+#[derive(Clone, Debug)]
+pub struct Macro {
+    arity: usize,
+    rest: bool,
+    procedure: Value,
+}
+
+#[derive(Clone, Debug)]
+pub enum Transformer {
+    Procedural(Macro),
+    Rules(SyntaxRules),
+}
+
+/// Expands macro calls in parsed forms before they reach the `Interpreter`.
+///
+/// `defmacro` bodies are ordinary code: each macro becomes a procedure that
+/// an `Interpreter` runs at expansion time. Macros are defined in a global
+/// environment of the expander's own, so they can use every primitive, but
+/// not the functions the program defines.
+#[derive(Debug)]
+pub struct Expander {
+    macros: HashMap<String, Transformer>,
+    environment: Environment,
+}
+
+impl Default for Expander {
+    fn default() -> Self {
+        Self {
+            macros: HashMap::new(),
+            environment: Environment::new(),
+        }
+    }
+}
+
+impl Expander {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the top-level macro definitions and expands every other form.
+    pub fn expand_program(
+        &mut self,
+        forms: &[ParserResult],
+    ) -> Result<Vec<ParserResult>, InterpretError> {
+        let mut expanded = Vec::with_capacity(forms.len());
+
+        for form in forms {
+            if !self.define_macro(form)? {
+                expanded.push(self.expand(form)?);
+            }
+        }
+
+        Ok(expanded)
+    }
+
+    /// Expands every macro call in `form`, including nested ones.
+    pub fn expand(&mut self, form: &ParserResult) -> Result<ParserResult, InterpretError> {
+        let items = match form {
+            ParserResult::Atom(_) | ParserResult::Vector(_) | ParserResult::Map(_) => {
+                return Ok(form.clone())
+            }
+            ParserResult::Expression(items) => items,
+        };
+
+        match head_name(items) {
+            Some(name) if self.macros.contains_key(name) => {
+                let expansion = self.expand_once(form)?;
+                self.expand(&expansion)
+            }
+            Some("quote") => Ok(form.clone()),
+            // Only the unquoted parts of a template are code
+            Some("quasiquote") if items.len() == 2 => Ok(list(vec![
+                items[0].clone(),
+                self.expand_template(&items[1], 1)?,
+            ])),
+            Some(name @ ("macroexpand-1" | "macroexpand")) => {
+                let quoted = match items.get(1) {
+                    Some(ParserResult::Expression(quote))
+                        if quote.len() == 2 && head_name(quote) == Some("quote") =>
+                    {
+                        &quote[1]
+                    }
+                    _ => {
+                        return Err(InterpretError::Expected(format!(
+                            "{} expects a quoted form",
+                            name
+                        )))
+                    }
+                };
+
+                let expansion = if name == "macroexpand-1" {
+                    self.expand_once(quoted)?
+                } else {
+                    self.expand_head(quoted)?
+                };
+
+                Ok(list(vec![symbol("quote"), expansion]))
+            }
+            Some("defmacro" | "define-macro" | "define-syntax") => Err(InterpretError::Expected(
+                "Macros can only be defined at top level".to_string(),
+            )),
+            // The parameter list of a function definition is not a call
+            Some("define") if items.len() > 3 => {
+                let mut expanded = items[..3].to_vec();
+                for item in &items[3..] {
+                    expanded.push(self.expand(item)?);
+                }
+                Ok(list(expanded))
+            }
+            Some("lambda") if items.len() > 2 => {
+                let mut expanded = items[..2].to_vec();
+                for item in &items[2..] {
+                    expanded.push(self.expand(item)?);
+                }
+                Ok(list(expanded))
+            }
+            // Only the initializers of let bindings are expressions
+            Some("let") if items.len() > 1 => {
+                let bindings = match &items[1] {
+                    ParserResult::Expression(bindings) => bindings
+                        .iter()
+                        .map(|binding| match binding {
+                            ParserResult::Expression(pair) if pair.len() == 2 => {
+                                Ok(list(vec![pair[0].clone(), self.expand(&pair[1])?]))
+                            }
+                            other => Ok(other.clone()),
+                        })
+                        .collect::<Result<Vec<_>, InterpretError>>()?,
+                    _ => return self.expand_items(items),
+                };
+
+                let mut expanded = vec![items[0].clone(), list(bindings)];
+                for item in &items[2..] {
+                    expanded.push(self.expand(item)?);
+                }
+                Ok(list(expanded))
+            }
+            _ => self.expand_items(items),
+        }
+    }
+
+    /// Expands `form` once if it is a macro call, like `macroexpand-1`. The
+    /// code the macro writes is placed where the call was.
+    pub fn expand_once(&mut self, form: &ParserResult) -> Result<ParserResult, InterpretError> {
+        let items = match form {
+            ParserResult::Expression(items) => items,
+            _ => return Ok(form.clone()),
+        };
+
+        let transformer = match head_name(items).and_then(|name| self.macros.get(name)) {
+            Some(transformer) => transformer.clone(),
+            None => return Ok(form.clone()),
+        };
+
+        let expansion = match transformer {
+            Transformer::Procedural(transformer) => {
+                self.call_macro(&items[0].to_string(), &transformer, &items[1..])?
+            }
+            Transformer::Rules(rules) => {
+                rules.expand(form, &mut |name| Symbol::gensym(name).name().to_string())?
+            }
+        };
+
+        Ok(match form.position() {
+            Some(position) => expansion.placed_at(position),
+            None => expansion,
+        })
+    }
+
+    /// Expands `form` until its head is no longer a macro, like `macroexpand`.
+    pub fn expand_head(&mut self, form: &ParserResult) -> Result<ParserResult, InterpretError> {
+        let mut current = form.clone();
+
+        loop {
+            let expansion = self.expand_once(&current)?;
+            if expansion == current {
+                return Ok(current);
+            }
+            current = expansion;
+        }
+    }
+
+    /// Expands the parts of a quasiquoted `template` that get evaluated.
+    fn expand_template(
+        &mut self,
+        template: &ParserResult,
+        depth: usize,
+    ) -> Result<ParserResult, InterpretError> {
+        let (items, inner) = match template {
+            ParserResult::Atom(_) => return Ok(template.clone()),
+            ParserResult::Expression(items) => match (head_name(items), items.len()) {
+                (Some("unquote" | "unquote-splicing"), 2) if depth == 1 => {
+                    return Ok(list(vec![items[0].clone(), self.expand(&items[1])?]))
+                }
+                (Some("unquote" | "unquote-splicing"), 2) => (items, depth - 1),
+                (Some("quasiquote"), 2) => (items, depth + 1),
+                _ => (items, depth),
+            },
+            ParserResult::Vector(items) | ParserResult::Map(items) => (items, depth),
+        };
+
+        let expanded = items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| self.expand_template(item, if index == 0 { depth } else { inner }))
+            .collect::<Result<Vec<_>, InterpretError>>()?;
+
+        Ok(match template {
+            ParserResult::Vector(_) => ParserResult::Vector(expanded),
+            ParserResult::Map(_) => ParserResult::Map(expanded),
+            _ => list(expanded),
+        })
+    }
+
+    fn expand_items(&mut self, items: &[ParserResult]) -> Result<ParserResult, InterpretError> {
+        let expanded = items
+            .iter()
+            .map(|item| self.expand(item))
+            .collect::<Result<Vec<_>, InterpretError>>()?;
+
+        Ok(list(expanded))
+    }
+
+    /// Registers `(defmacro name (params...) body...)`,
+    /// `(define-macro (name params...) body...)` or
+    /// `(define-syntax name (syntax-rules ...))`; returns false for any other form.
+    fn define_macro(&mut self, form: &ParserResult) -> Result<bool, InterpretError> {
+        let items = match form {
+            ParserResult::Expression(items) => items,
+            _ => return Ok(false),
+        };
+
+        if head_name(items) == Some("define-syntax") {
+            let rules = match (items.get(1), items.get(2), items.len()) {
+                (Some(ParserResult::Atom(name)), Some(ParserResult::Expression(spec)), 3)
+                    if head_name(spec) == Some("syntax-rules") =>
+                {
+                    (name.value.clone(), SyntaxRules::parse(&spec[1..])?)
+                }
+                _ => {
+                    return Err(InterpretError::Expected(
+                        "Expected (define-syntax name (syntax-rules ...))".to_string(),
+                    ))
+                }
+            };
+
+            self.macros.insert(rules.0, Transformer::Rules(rules.1));
+            return Ok(true);
+        }
+
+        let (name, params, body) = match (head_name(items), items.get(1), items.get(2)) {
+            (
+                Some("defmacro"),
+                Some(ParserResult::Atom(name)),
+                Some(ParserResult::Expression(params)),
+            ) => (name.value.clone(), params.as_slice(), &items[3..]),
+            (Some("define-macro"), Some(ParserResult::Expression(signature)), _)
+                if !signature.is_empty() =>
+            {
+                (signature[0].to_string(), &signature[1..], &items[2..])
+            }
+            (Some("defmacro" | "define-macro"), _, _) => {
+                return Err(InterpretError::Expected(
+                    "Expected (defmacro name (params...) body...)".to_string(),
+                ))
+            }
+            _ => return Ok(false),
+        };
+
+        if body.is_empty() {
+            return Err(InterpretError::Expected(format!(
+                "Expected a body for macro {}",
+                name
+            )));
+        }
+
+        let mut names = params.iter().map(|param| param.to_string());
+        let mut fixed = vec![];
+        let mut rest = None;
+
+        while let Some(param) = names.next() {
+            if param == "&rest" || param == "." {
+                rest = names.next();
+                if rest.is_none() || names.next().is_some() {
+                    return Err(InterpretError::Expected(format!(
+                        "Expected a single rest parameter after {}",
+                        param
+                    )));
+                }
+            } else {
+                fixed.push(symbol(&param));
+            }
+        }
+
+        let arity = fixed.len();
+        let mut params = fixed;
+        if let Some(rest) = &rest {
+            params.extend([symbol("."), symbol(rest)]);
+        }
+
+        let mut lambda = vec![symbol("lambda"), list(params)];
+        lambda.extend(body.iter().cloned());
+        let lambda = self.expand(&list(lambda))?;
+        let procedure =
+            Interpreter::new(slice::from_ref(&lambda), &self.environment).interpret_expression()?;
+
+        self.macros.insert(
+            name,
+            Transformer::Procedural(Macro {
+                arity,
+                rest: rest.is_some(),
+                procedure,
+            }),
+        );
+
+        Ok(true)
+    }
+
+    fn call_macro(
+        &mut self,
+        name: &str,
+        transformer: &Macro,
+        args: &[ParserResult],
+    ) -> Result<ParserResult, InterpretError> {
+        let arity = transformer.arity;
+
+        if args.len() < arity || (!transformer.rest && args.len() != arity) {
+            return Err(InterpretError::Expected(format!(
+                "Macro {} expects {} arguments, got {}",
+                name,
+                arity,
+                args.len()
+            )));
+        }
+
+        let args = args.iter().map(Value::from_datum).collect();
+        let expansion =
+            Interpreter::new(&[], &self.environment).apply(&transformer.procedure, args)?;
+
+        expansion.to_datum().ok_or_else(|| {
+            InterpretError::Expected(format!(
+                "Macro {} expanded to {}, which is not code",
+                name, expansion
+            ))
+        })
+    }
+}
+
+fn head_name(items: &[ParserResult]) -> Option<&str> {
+    match items.first() {
+        Some(ParserResult::Atom(element)) => Some(element.value.as_str()),
+        _ => None,
+    }
+}
+
+fn symbol(name: &str) -> ParserResult {
+    ParserResult::Atom(Element {
+        kind: classify(name),
+        value: name.to_string(),
+        position: None,
+    })
+}
+
+fn list(items: Vec<ParserResult>) -> ParserResult {
+    ParserResult::Expression(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::run;
+
+    const MY_IF: &str = "
+        (defmacro my-if (test then else)
+            `(cond (,test ,then) (else ,else)))
+        (defmacro my-unless (test then else)
+            `(my-if ,test ,else ,then))";
+
+    #[test]
+    fn expands_quasiquote_templates() {
+        let source = format!("{} (my-unless (< 5 3) 1 2)", MY_IF);
+
+        assert_eq!(run(&source), Ok("1".to_string()));
+    }
+
+    #[test]
+    fn splices_rest_arguments() {
+        let source = "
+            (define-macro (when test . body)
+                `(cond (,test (begin ,@body)) (else false)))
+            (define x 0)
+            (when (< 1 2) (set! x 5) (+ x 1))";
+
+        assert_eq!(run(source), Ok("6".to_string()));
+    }
+
+    #[test]
+    fn gensym_keeps_introduced_bindings_apart() {
+        let source = "
+            (defmacro swap! (a b)
+                (let ((tmp (gensym)))
+                    `(let ((,tmp ,a)) (set! ,a ,b) (set! ,b ,tmp))))
+            (define tmp 1)
+            (define other 2)
+            (swap! tmp other)
+            tmp
+            other";
+
+        assert_eq!(run(source), Ok("2 1".to_string()));
+    }
+
+    #[test]
+    fn macroexpand_one_step_or_until_the_head_is_not_a_macro() {
+        let once = format!("{} (macroexpand-1 '(my-unless x 1 2))", MY_IF);
+        let full = format!("{} (macroexpand '(my-unless x 1 2))", MY_IF);
+
+        assert_eq!(run(&once), Ok("(my-if x 2 1)".to_string()));
+        assert_eq!(run(&full), Ok("(cond (x 2) (else 1))".to_string()));
+    }
+
+    #[test]
+    fn bodies_are_evaluated_like_any_other_code() {
+        let source = r#"
+            (defmacro repeat (n &rest body)
+                (define copies (n) (if (= n 0) '() (append body (copies (- n 1)))))
+                (cons 'begin (copies n)))
+            (defmacro named (name)
+                (string->symbol (string-append "get-" (symbol->string name))))
+            (define count 0)
+            (repeat 3 (set! count (+ count 1)))
+            (define get-count () count)
+            ((named count))
+            (macroexpand-1 '(repeat 2 x))"#;
+
+        assert_eq!(run(source), Ok("3 (begin x x)".to_string()));
+        assert_eq!(
+            run("(defmacro bad () (lambda () 1)) (bad)"),
+            Err(InterpretError::Expected(
+                "Macro bad expanded to #<procedure lambda>, which is not code".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_calls_with_the_wrong_arity() {
+        let source = format!("{} (my-if 1 2)", MY_IF);
+
+        assert_eq!(
+            run(&source),
+            Err(InterpretError::Expected(
+                "Macro my-if expects 3 arguments, got 2".to_string()
+            ))
+        );
+    }
+}
@@ -1,85 +1,295 @@
-mod interpreter;
-mod parser;
-mod tokenizer;
-mod tokens;
-
 use std::{
-    collections::HashMap,
-    io::{stdin, stdout, Write},
+    env, fs,
+    io::{self, stdin, stdout, Read, Write},
+    path::{Path, PathBuf},
     process::exit,
+    thread,
 };
 
-use parsenator::*;
-pub use parser::ParserResult;
-
-use crate::{
-    interpreter::{Interpret, Interpreter},
-    parser::my_parser,
+use ilisp::{
+    formatter,
+    interpreter::{self, Environment, Interpret, Interpreter},
+    macros::Expander,
+    parser, printer, testing,
+    value::Value,
 };
 
-fn read() -> String {
+fn read() -> Option<String> {
     print!("> ");
 
     let mut input = String::new();
     let _ = stdout().flush();
 
-    stdin()
+    match stdin()
         .read_line(&mut input)
-        .expect("Error reading from STDIN");
+        .expect("Error reading from STDIN")
+    {
+        0 => None,
+        _ => Some(input),
+    }
+}
 
-    input
+/// Reads, expands and evaluates every form in `input`, returning their values.
+fn eval(
+    input: &str,
+    environment: &Environment,
+    expander: &mut Expander,
+) -> Result<Vec<Value>, String> {
+    let forms = parser::read(input).map_err(|errors| {
+        errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    })?;
+    let program = expander
+        .expand_program(&forms)
+        .map_err(|error| error.to_string())?;
+
+    let mut interpreter = Interpreter::new(&program, environment);
+    program
+        .iter()
+        .map(|_| interpreter.interpret_expression())
+        .collect::<Result<_, _>>()
+        .map_err(|error| error.to_string())
 }
 
-fn eval(input: String) {
-    match input.as_str().trim() {
-        "(exit)" => {
-            exit(0);
+fn print(values: &[Value]) {
+    for value in values {
+        if *value != Value::Unspecified {
+            println!("{}", printer::write(value));
         }
-        _ => println!("Input: {:?}", tokenizer::tokenize(&input)),
     }
 }
 
-fn print() {}
-
 fn repl() {
-    loop {
-        let input = read();
-        eval(input);
-        print();
+    let environment = Environment::new();
+    let mut expander = Expander::new();
+
+    while let Some(input) = read() {
+        if input.trim() == "(exit)" {
+            return;
+        }
+
+        match eval(&input, &environment, &mut expander) {
+            Ok(values) => print(&values),
+            Err(error) => eprintln!("{}", error),
+        }
     }
 }
 
-fn main() {
-    let parser = my_parser().parse(
-        "
+/// Runs the program in the file at `path`; returns the exit code.
+fn run(path: &str) -> i32 {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            return 1;
+        }
+    };
 
-    (define doublen (n)
-        (* n 2))
+    match eval(&source, &Environment::new(), &mut Expander::new()) {
+        Ok(_) => 0,
+        Err(error) => {
+            eprintln!("{}", error);
+            1
+        }
+    }
+}
 
-    (define fib (n)
-        (if (< n 2)
-            n
-            (+ (fib (- n 1))
-                (fib (- n 2)))))
+/// `ilisp fmt [--check] [files...]`: rewrites each file in the canonical
+/// layout, or with `--check` only names the files that would change. Without
+/// files it formats the standard input to the standard output. Returns the
+/// exit code: 1 if a file has syntax errors or, with `--check`, would change.
+fn fmt(args: &[String]) -> i32 {
+    let check = args.iter().any(|arg| arg == "--check");
+    let paths: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
 
-    (define fact (n)
-        (if (<= n 1)
-            1
-            (* n (fact (- n 1)))))
+    if paths.is_empty() {
+        let mut source = String::new();
+        if let Err(error) = stdin().read_to_string(&mut source) {
+            eprintln!("<stdin>: {}", error);
+            return 1;
+        }
 
-    (doublen 5)
-    (fib 7)
-    (fact 5)
-    ",
-    );
+        return match formatter::format_source(&source, formatter::MAX_WIDTH) {
+            Ok(formatted) if check && formatted != source => {
+                println!("<stdin> is not formatted");
+                1
+            }
+            Ok(_) if check => 0,
+            Ok(formatted) => {
+                print!("{}", formatted);
+                0
+            }
+            Err(errors) => {
+                errors
+                    .iter()
+                    .for_each(|error| eprintln!("<stdin>:{}", error));
+                1
+            }
+        };
+    }
 
-    // let parser = my_parser().parse("(+ 0.5 5.7)");
+    let mut status = 0;
+    for path in paths {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("{}: {}", path, error);
+                status = 1;
+                continue;
+            }
+        };
 
-    println!("Parser result: {:?}\n", parser);
-    let mut env = interpreter::Environment {
-        scopes: vec![HashMap::new()],
-        level: 0,
+        match formatter::format_source(&source, formatter::MAX_WIDTH) {
+            Ok(formatted) if formatted == source => {}
+            Ok(_) if check => {
+                println!("{} is not formatted", path);
+                status = 1;
+            }
+            Ok(formatted) => {
+                if let Err(error) = fs::write(path, formatted) {
+                    eprintln!("{}: {}", path, error);
+                    status = 1;
+                }
+            }
+            Err(errors) => {
+                errors
+                    .iter()
+                    .for_each(|error| eprintln!("{}:{}", path, error));
+                status = 1;
+            }
+        }
+    }
+
+    status
+}
+
+/// `ilisp test [paths...]`: runs the `deftest`s in the named files and in
+/// every `*_test.lisp` or `*-test.lisp` file under the named directories, by
+/// default the current one. Returns the exit code: 1 if a test failed or a
+/// file could not be loaded.
+fn test(args: &[String]) -> i32 {
+    let roots = match args.is_empty() {
+        true => vec![".".to_string()],
+        false => args.to_vec(),
     };
-    let mut interpreter = Interpreter::new(parser.unwrap().1, &mut env);
-    println!("Interpreter result: {:?}", interpreter.get_result());
+
+    let mut files = vec![];
+    for root in &roots {
+        if let Err(error) = test_files(Path::new(root), &mut files) {
+            eprintln!("{}: {}", root, error);
+            return 1;
+        }
+    }
+
+    let (mut passed, mut failed, mut status) = (0, 0, 0);
+    for file in files {
+        let path = file.strip_prefix(".").unwrap_or(&file).display();
+        let source = match fs::read_to_string(&file) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("{}: {}", path, error);
+                status = 1;
+                continue;
+            }
+        };
+
+        let results = match testing::run_tests(&source) {
+            Ok(results) => results,
+            Err(error) => {
+                eprintln!("{}:{}", path, error);
+                status = 1;
+                continue;
+            }
+        };
+
+        for result in results {
+            let location = format!("{}:{}:{}", path, result.line, result.column);
+
+            match result.failure {
+                None => {
+                    println!("PASS {}: {}", location, result.name);
+                    passed += 1;
+                }
+                Some(failure) => {
+                    println!("FAIL {}: {}", location, result.name);
+                    println!(
+                        "  {}:{}:{}: {}",
+                        path,
+                        failure.line,
+                        failure.column,
+                        failure.message.replace('\n', "\n  ")
+                    );
+                    failed += 1;
+                    status = 1;
+                }
+            }
+        }
+    }
+
+    println!("\n{} passed, {} failed", passed, failed);
+    status
+}
+
+/// Adds `path` to `files` if it is a file; if it is a directory, adds the test
+/// files anywhere under it, skipping hidden ones.
+fn test_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for entry in entries {
+        let name = entry.file_name().unwrap_or_default().to_string_lossy();
+
+        if name.starts_with('.') {
+            continue;
+        } else if entry.is_dir() {
+            test_files(&entry, files)?;
+        } else if name.ends_with("_test.lisp") || name.ends_with("-test.lisp") {
+            files.push(entry);
+        }
+    }
+
+    Ok(())
+}
+
+/// Native stack for the interpreter thread, so that programs can recurse up
+/// to the interpreter's recursion limit rather than its default stack budget.
+const INTERPRETER_STACK_SIZE: usize = 256 * 1024 * 1024;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("fmt") {
+        exit(fmt(&args[1..]));
+    }
+
+    // `ilisp` starts the REPL, `ilisp program.lisp` runs a file
+    let interpreter = thread::Builder::new()
+        .stack_size(INTERPRETER_STACK_SIZE)
+        .spawn(move || {
+            interpreter::set_stack_budget(INTERPRETER_STACK_SIZE / 2);
+
+            match args.first().map(String::as_str) {
+                Some("test") => test(&args[1..]),
+                Some(path) => run(path),
+                None => {
+                    repl();
+                    0
+                }
+            }
+        })
+        .expect("Error starting the interpreter thread");
+
+    match interpreter.join() {
+        Ok(code) => exit(code),
+        Err(_) => exit(1),
+    }
 }
@@ -0,0 +1,277 @@
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use crate::value::Value;
+
+/// A hash table from values to values. Keys are compared by contents, like
+/// `equal?` in other Lisps, and the entries stay in the order their keys were
+/// first inserted, so printing and iterating a map is deterministic.
+#[derive(Clone, Default)]
+pub struct Map {
+    /// In insertion order. A removed entry leaves a `None` behind, so the
+    /// others keep their indices until there are enough gaps to compact.
+    entries: Vec<Option<(Value, Value)>>,
+    /// How many of `entries` are not removed.
+    len: usize,
+    /// The indices in `entries` of the keys with each hash.
+    buckets: HashMap<u64, Vec<usize>>,
+}
+
+fn hash(key: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn entry(&self, index: usize) -> &(Value, Value) {
+        self.entries[index]
+            .as_ref()
+            .expect("buckets only hold live entries")
+    }
+
+    fn position(&self, key: &Value) -> Option<usize> {
+        self.find(key, hash(key))
+    }
+
+    fn find(&self, key: &Value, hash: u64) -> Option<usize> {
+        self.buckets
+            .get(&hash)?
+            .iter()
+            .copied()
+            .find(|&index| self.entry(index).0 == *key)
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        self.position(key).map(|index| &self.entry(index).1)
+    }
+
+    /// Sets the value of `key`. A new key goes after the others.
+    pub fn insert(&mut self, key: Value, value: Value) {
+        let hash = hash(&key);
+        let index = self.find(&key, hash);
+        self.insert_found(key, value, hash, index);
+    }
+
+    /// `insert` into the map in `cell`. The key may contain the map itself,
+    /// so it is hashed and compared before the map is borrowed mutably.
+    pub fn set(cell: &RefCell<Map>, key: Value, value: Value) {
+        let hash = hash(&key);
+        let index = cell.borrow().find(&key, hash);
+        cell.borrow_mut().insert_found(key, value, hash, index);
+    }
+
+    fn insert_found(&mut self, key: Value, value: Value, hash: u64, index: Option<usize>) {
+        match index {
+            Some(index) => {
+                if let Some(entry) = &mut self.entries[index] {
+                    entry.1 = value;
+                }
+            }
+            None => {
+                self.buckets
+                    .entry(hash)
+                    .or_default()
+                    .push(self.entries.len());
+                self.entries.push(Some((key, value)));
+                self.len += 1;
+            }
+        }
+    }
+
+    /// Removes the entry of `key`, keeping the others in order, and returns
+    /// its value.
+    pub fn remove(&mut self, key: &Value) -> Option<Value> {
+        let hash = hash(key);
+        let index = self.find(key, hash)?;
+        self.remove_found(hash, index)
+    }
+
+    /// `remove` from the map in `cell`, looking the key up first like `set`.
+    pub fn delete(cell: &RefCell<Map>, key: &Value) -> Option<Value> {
+        let hash = hash(key);
+        let index = cell.borrow().find(key, hash)?;
+        cell.borrow_mut().remove_found(hash, index)
+    }
+
+    fn remove_found(&mut self, hash: u64, index: usize) -> Option<Value> {
+        let bucket = self.buckets.get_mut(&hash)?;
+        bucket.retain(|&other| other != index);
+        if bucket.is_empty() {
+            self.buckets.remove(&hash);
+        }
+
+        let (_, value) = self.entries[index].take()?;
+        self.len -= 1;
+        if self.entries.len() > 2 * self.len {
+            self.compact();
+        }
+        Some(value)
+    }
+
+    /// Drops the gaps removed entries left, renumbering the buckets. Only
+    /// done once gaps are at least half of `entries`, so removing stays
+    /// constant time on average.
+    fn compact(&mut self) {
+        let mut renumbered = vec![0; self.entries.len()];
+        let mut next = 0;
+        for (index, entry) in self.entries.iter().enumerate() {
+            if entry.is_some() {
+                renumbered[index] = next;
+                next += 1;
+            }
+        }
+
+        self.entries.retain(Option::is_some);
+        for bucket in self.buckets.values_mut() {
+            for index in bucket.iter_mut() {
+                *index = renumbered[*index];
+            }
+        }
+    }
+
+    /// Whether the maps have the same keys, in any order, with values that
+    /// are the `same`.
+    pub fn same_entries(&self, other: &Map, mut same: impl FnMut(&Value, &Value) -> bool) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key).is_some_and(|theirs| same(value, theirs)))
+    }
+
+    /// The entries in order.
+    pub fn iter(&self) -> impl Iterator<Item = &(Value, Value)> {
+        self.entries.iter().flatten()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Value> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.iter().map(|(_, value)| value)
+    }
+}
+
+impl FromIterator<(Value, Value)> for Map {
+    fn from_iter<T: IntoIterator<Item = (Value, Value)>>(entries: T) -> Self {
+        let mut map = Map::new();
+        for (key, value) in entries {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+/// Maps are equal when they have the same entries, in any order.
+impl PartialEq for Map {
+    fn eq(&self, other: &Self) -> bool {
+        self.same_entries(other, |a, b| a == b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> Value {
+        Value::symbol(name)
+    }
+
+    #[test]
+    fn keeps_insertion_order() {
+        let mut map: Map = [
+            (key("a"), Value::Number(1.0)),
+            (key("b"), Value::Number(2.0)),
+        ]
+        .into_iter()
+        .collect();
+        map.insert(key("c"), Value::Number(3.0));
+        map.insert(key("a"), Value::Number(10.0));
+
+        assert_eq!(
+            map.keys().cloned().collect::<Vec<_>>(),
+            [key("a"), key("b"), key("c")]
+        );
+        assert_eq!(map.remove(&key("b")), Some(Value::Number(2.0)));
+        assert_eq!(map.remove(&key("b")), None);
+        assert_eq!(
+            map.iter().cloned().collect::<Vec<_>>(),
+            [
+                (key("a"), Value::Number(10.0)),
+                (key("c"), Value::Number(3.0))
+            ]
+        );
+        assert_eq!(map.get(&key("c")), Some(&Value::Number(3.0)));
+    }
+
+    #[test]
+    fn removing_most_entries_keeps_the_rest_reachable() {
+        let mut map: Map = (0..100)
+            .map(|n| (Value::Number(n as f32), Value::Number(n as f32 * 10.0)))
+            .collect();
+        for n in (0..100).filter(|n| n % 10 != 0) {
+            assert!(map.remove(&Value::Number(n as f32)).is_some());
+        }
+        map.insert(Value::Number(5.0), Value::Number(-1.0));
+
+        assert_eq!(map.len(), 11);
+        assert_eq!(map.get(&Value::Number(90.0)), Some(&Value::Number(900.0)));
+        assert_eq!(map.get(&Value::Number(5.0)), Some(&Value::Number(-1.0)));
+        assert_eq!(
+            map.keys().cloned().collect::<Vec<_>>(),
+            (0..10)
+                .map(|n| Value::Number(n as f32 * 10.0))
+                .chain([Value::Number(5.0)])
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn keys_are_compared_by_contents() {
+        let mut map = Map::new();
+        map.insert(Value::List(vec![key("x"), Value::Number(1.0)]), key("list"));
+        map.insert(Value::vector(vec![Value::Number(0.0)]), key("vector"));
+        map.insert(Value::Number(0.0), key("zero"));
+
+        assert_eq!(
+            map.get(&Value::List(vec![key("x"), Value::Number(1.0)])),
+            Some(&key("list"))
+        );
+        assert_eq!(
+            map.get(&Value::vector(vec![Value::Number(-0.0)])),
+            Some(&key("vector"))
+        );
+        assert_eq!(map.get(&Value::Number(-0.0)), Some(&key("zero")));
+        assert_eq!(map.get(&Value::Str("x".to_string())), None);
+    }
+
+    #[test]
+    fn equality_ignores_order() {
+        let a: Map = [(key("a"), key("1")), (key("b"), key("2"))]
+            .into_iter()
+            .collect();
+        let b: Map = [(key("b"), key("2")), (key("a"), key("1"))]
+            .into_iter()
+            .collect();
+        let c: Map = [(key("a"), key("1"))].into_iter().collect();
+
+        assert!(a == b);
+        assert!(a != c);
+    }
+}
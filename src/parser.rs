@@ -1,196 +1,331 @@
-use parsenator::*;
+use std::fmt;
+
+use crate::syntax;
+use crate::tokenizer::{escape, escape_symbol, write_symbol, SyntaxError};
+use crate::tokens::Position;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum ParserResult {
     Atom(Element),
     Expression(Vec<ParserResult>),
+    /// `#(...)` or `[...]`: a vector constant.
+    Vector(Vec<ParserResult>),
+    /// `{key value ...}`: a map constant, its keys and values alternating.
+    Map(Vec<ParserResult>),
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Kind {
     Identifier,
+    /// `:name`, which evaluates to itself.
+    Keyword,
     Literal,
+    /// A string literal; the value holds its contents without the quotes.
+    String,
     Function,
     Condition,
-    Binary,
-    Comparison,
-    Logical,
-    Unary,
-    Format,
     Bool,
+    Set,
+    Begin,
+    Let,
+    Cond,
+    Quote,
+    Quasiquote,
+    Lambda,
+    When,
+    Unless,
+    And,
+    Or,
+    Deftest,
+    AssertError,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(Debug, Clone)]
 pub struct Element {
     pub kind: Kind,
     pub value: String,
+    /// Where the atom was read, for tracebacks; `None` for atoms made up by
+    /// macros and the expander.
+    pub position: Option<Position>,
+}
+
+/// Atoms are equal when they read the same, wherever they were read.
+impl PartialEq for Element {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.value == other.value
+    }
 }
 
-impl ToString for ParserResult {
-    fn to_string(&self) -> String {
+impl fmt::Display for ParserResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            ParserResult::Atom(s) => s.value.clone(),
-            ParserResult::Expression(items) => items
-                .iter()
-                .map(|x| x.to_string())
-                .collect::<Vec<String>>()
-                .join(" "),
+            ParserResult::Atom(s) => write!(f, "{}", s.value),
+            ParserResult::Expression(items) => {
+                let items: Vec<String> = items.iter().map(|x| x.to_string()).collect();
+                write!(f, "{}", items.join(" "))
+            }
+            ParserResult::Vector(items) => {
+                let items: Vec<String> = items.iter().map(|x| x.to_string()).collect();
+                write!(f, "#({})", items.join(" "))
+            }
+            ParserResult::Map(items) => {
+                let items: Vec<String> = items.iter().map(|x| x.to_string()).collect();
+                write!(f, "{{{}}}", items.join(" "))
+            }
         }
     }
 }
 
-pub fn my_parser() -> impl Parser<'static, Vec<ParserResult>> {
-    move |input: &'static str| match one_or_more(expression()).parse(input) {
-        Ok((remaining, result)) => {
-            let mapped: Vec<String> = result
-                .iter()
-                .filter(|x| !x.to_string().is_empty())
-                .map(|x| x.to_string())
-                .collect();
-
-            let converted = convert(&mapped);
-            display_tree(&converted, 0);
-            Ok((remaining, converted))
+impl ParserResult {
+    /// Where the form's first atom was read.
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            ParserResult::Atom(element) => element.position,
+            ParserResult::Expression(items)
+            | ParserResult::Vector(items)
+            | ParserResult::Map(items) => items.iter().find_map(ParserResult::position),
         }
-        Err(e) => Err(ParseError::Message(format!("{:?}", e))),
     }
-}
 
-pub fn parse_list(tokens: &[String]) -> Result<(Vec<ParserResult>, &[String]), String> {
-    let mut result = Vec::new();
-    let mut remaining = tokens;
+    /// The form with the atoms that have no position placed at `position`.
+    pub fn placed_at(self, position: Position) -> ParserResult {
+        let place = |items: Vec<ParserResult>| {
+            items
+                .into_iter()
+                .map(|item| item.placed_at(position))
+                .collect()
+        };
 
-    while !remaining.is_empty() {
-        match remaining[0].as_str() {
-            "(" => {
-                let (nested, new_remaining) = parse_list(&remaining[1..])?;
-                result.push(ParserResult::Expression(nested));
-                remaining = new_remaining;
-            }
-            ")" => {
-                return Ok((result, &remaining[1..]));
-            }
-            token => {
-                let binary = vec!["+", "-", "/", "*"];
-                let unary = vec!["!"];
-                let logical_bool = vec!["&", "|"];
-                let logical_int = vec!["<", "<=", ">", ">=", "=="];
-
-                let mut value = token;
-                let kind = match value {
-                    "define" => Kind::Function,
-                    "if" => Kind::Condition,
-                    "format" => Kind::Format,
-                    "<" => {
-                        if remaining[1].as_str() == "=" {
-                            value = "<=";
-                            remaining = &remaining[1..];
-                        }
-                        Kind::Comparison
-                    }
-                    ">" => {
-                        if remaining[1].as_str() == "=" {
-                            value = ">=";
-                            remaining = &remaining[1..];
-                        }
-                        Kind::Comparison
-                    }
-                    "=" => {
-                        value = "==";
-                        remaining = &remaining[1..];
-                        Kind::Comparison
-                    }
-                    "*" => {
-                        if remaining[1].as_str() == "*" {
-                            value = "**";
-                            remaining = &remaining[1..];
-                        }
-                        Kind::Binary
-                    }
-                    _ if value == "true" || value == "false" => Kind::Bool,
-                    _ if logical_bool.contains(&value) => Kind::Logical,
-                    _ if logical_int.contains(&value) => Kind::Comparison,
-                    _ if (value.starts_with("\"") && value.ends_with("\""))
-                        | value.bytes().all(|c| c.is_ascii_digit()) =>
-                    {
-                        Kind::Literal
-                    }
-                    _ if unary.contains(&value) => Kind::Unary,
-                    _ if binary.contains(&value) => Kind::Binary,
-                    _ => Kind::Identifier,
-                };
-
-                result.push(ParserResult::Atom(Element {
-                    kind,
-                    value: value.to_string(),
-                }));
-
-                remaining = &remaining[1..];
-            }
+        match self {
+            ParserResult::Atom(element) => ParserResult::Atom(Element {
+                position: element.position.or(Some(position)),
+                ..element
+            }),
+            ParserResult::Expression(items) => ParserResult::Expression(place(items)),
+            ParserResult::Vector(items) => ParserResult::Vector(place(items)),
+            ParserResult::Map(items) => ParserResult::Map(place(items)),
         }
     }
 
-    Ok((result, remaining))
-}
-
-pub fn display_tree(tokens: &[ParserResult], indent: usize) {
-    let padding_level = indent;
-    const PADDING_SIZE: usize = 4;
-
-    for token in tokens {
-        match token {
-            ParserResult::Atom(element) => {
-                println!(
-                    "{:indent$}{}",
-                    "",
-                    element.value,
-                    indent = padding_level * PADDING_SIZE
-                );
+    /// Renders the form back as source text, parentheses included.
+    pub fn to_source(&self) -> String {
+        match self {
+            ParserResult::Atom(element) if element.kind == Kind::String => {
+                format!("\"{}\"", escape(&element.value))
             }
-            ParserResult::Expression(parser_results) => {
-                println!("{:indent$}(", "", indent = padding_level * PADDING_SIZE);
-
-                display_tree(parser_results, padding_level + 1);
-
-                println!("{:indent$})", "", indent = padding_level * PADDING_SIZE);
+            ParserResult::Atom(element) if element.kind == Kind::Identifier => {
+                match classify(&element.value) {
+                    Kind::Identifier => write_symbol(&element.value),
+                    // Read from `|define|` and the like
+                    _ => escape_symbol(&element.value),
+                }
+            }
+            ParserResult::Atom(element) => element.value.clone(),
+            ParserResult::Expression(items) => {
+                let items: Vec<String> = items.iter().map(|x| x.to_source()).collect();
+                format!("({})", items.join(" "))
+            }
+            ParserResult::Vector(items) => {
+                let items: Vec<String> = items.iter().map(|x| x.to_source()).collect();
+                format!("#({})", items.join(" "))
+            }
+            ParserResult::Map(items) => {
+                let items: Vec<String> = items.iter().map(|x| x.to_source()).collect();
+                format!("{{{}}}", items.join(" "))
             }
         }
     }
 }
 
-pub fn convert(tokens: &[String]) -> Vec<ParserResult> {
-    match parse_list(tokens) {
-        Ok((result, _)) => result,
-        Err(_) => vec![],
+/// Reads every datum in `source`, or fails with every syntax error in it.
+///
+/// The data are derived from the lossless syntax tree (see [`syntax::parse`]):
+/// the tokenizer does all the lexing, so multi-character symbols like `<=` or
+/// `**` arrive as single tokens, and the quote shorthands `'x`, `` `x ``, `,x`
+/// and `,@x` become `(quote x)` and so on. `#(...)` and `[...]` both read as
+/// vectors, and `{...}` as a map.
+pub fn read(source: &str) -> Result<Vec<ParserResult>, Vec<SyntaxError>> {
+    match read_partial(source) {
+        (data, errors) if errors.is_empty() => Ok(data),
+        (_, errors) => Err(errors),
     }
 }
 
-pub fn atom<'a>() -> Box<dyn Parser<'a, Types<'a>> + 'a> {
-    choice(vec![
-        skip(spaces()),
-        float(),
-        word(),
-        alpha_num_word(),
-        digits(),
-        any_char(),
-        spaces(),
-    ])
+/// Reads as much of `source` as it can, for tools that want a tree even when
+/// the text is malformed. Returns the data read alongside every syntax error,
+/// in source order: a stray `)` is skipped, an unclosed list ends with the
+/// input, and a quote with nothing to quote is dropped.
+pub fn read_partial(source: &str) -> (Vec<ParserResult>, Vec<SyntaxError>) {
+    let (tree, errors) = syntax::parse(source);
+    (tree.data(), errors)
 }
 
-fn expression() -> Box<dyn Parser<'static, Types<'static>> + 'static> {
-    choice(vec![
-        atom(),
-        Box::new(move |input| paren_expr().parse(input)),
-    ])
+/// Kind of a symbol, judged from its spelling alone.
+pub fn classify(value: &str) -> Kind {
+    match value {
+        "define" => Kind::Function,
+        "if" => Kind::Condition,
+        "set!" => Kind::Set,
+        "begin" => Kind::Begin,
+        "let" => Kind::Let,
+        "cond" => Kind::Cond,
+        "quote" => Kind::Quote,
+        "quasiquote" => Kind::Quasiquote,
+        "lambda" => Kind::Lambda,
+        "when" => Kind::When,
+        "unless" => Kind::Unless,
+        "and" => Kind::And,
+        "or" => Kind::Or,
+        "deftest" => Kind::Deftest,
+        "assert-error" => Kind::AssertError,
+        "true" | "false" => Kind::Bool,
+        _ if value.len() > 1 && value.starts_with(':') => Kind::Keyword,
+        _ => Kind::Identifier,
+    }
 }
 
-fn paren_expr() -> Box<dyn Parser<'static, Types<'static>> + 'static> {
-    Box::new(between(
-        char('('),
-        Box::new(move |input| {
-            let mapped_parser = map(one_or_more(expression()), Types::TypesVec);
-            mapped_parser.parse(input)
-        }),
-        char(')'),
-    ))
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::Span;
+
+    fn source(text: &str) -> Vec<String> {
+        read(text)
+            .unwrap()
+            .iter()
+            .map(ParserResult::to_source)
+            .collect()
+    }
+
+    #[test]
+    fn multi_character_operators_are_single_symbols() {
+        assert_eq!(
+            read("(<= a b) (** 2 3)").unwrap()[1],
+            ParserResult::Expression(vec![
+                ParserResult::Atom(Element {
+                    kind: Kind::Identifier,
+                    value: "**".to_string(),
+                    position: None,
+                }),
+                ParserResult::Atom(Element {
+                    kind: Kind::Literal,
+                    value: "2".to_string(),
+                    position: None,
+                }),
+                ParserResult::Atom(Element {
+                    kind: Kind::Literal,
+                    value: "3".to_string(),
+                    position: None,
+                }),
+            ])
+        );
+        assert_eq!(source("(<= a b) (>= -1 x) (== = y)")[2], "(== = y)");
+    }
+
+    #[test]
+    fn atoms_take_their_kind_from_the_token() {
+        let kinds: Vec<Kind> = read(r#"1.5 "if" if true x"#)
+            .unwrap()
+            .into_iter()
+            .map(|datum| match datum {
+                ParserResult::Atom(element) => element.kind,
+                _ => unreachable!(),
+            })
+            .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                Kind::Literal,
+                Kind::String,
+                Kind::Condition,
+                Kind::Bool,
+                Kind::Identifier
+            ]
+        );
+    }
+
+    #[test]
+    fn quote_shorthands() {
+        assert_eq!(
+            source("'x `(a ,b ,@c) '()"),
+            vec![
+                "(quote x)",
+                "(quasiquote (a (unquote b) (unquote-splicing c)))",
+                "(quote ())"
+            ]
+        );
+    }
+
+    #[test]
+    fn vectors() {
+        assert_eq!(
+            source("#(1 [2 \"three\"]) [] '[a]"),
+            vec!["#(1 #(2 \"three\"))", "#()", "(quote #(a))"]
+        );
+    }
+
+    #[test]
+    fn keywords_start_with_a_colon() {
+        assert_eq!(classify(":name"), Kind::Keyword);
+        assert_eq!(classify(":"), Kind::Identifier);
+        assert_eq!(classify("a:b"), Kind::Identifier);
+    }
+
+    #[test]
+    fn maps() {
+        assert_eq!(
+            source("{:a 1 :b {c [d]}} {} '{x (y)}"),
+            vec!["{:a 1 :b {c #(d)}}", "{}", "(quote {x (y)})"]
+        );
+        assert_eq!(
+            read("{:a 1 :b}").unwrap_err()[0].to_string(),
+            "1:1: Expected a value for every key in {"
+        );
+    }
+
+    #[test]
+    fn reports_unbalanced_parentheses() {
+        let message = |text| read(text).unwrap_err()[0].to_string();
+
+        assert_eq!(message("(+ 1\n  (f 2)"), "1:1: Unclosed (");
+        assert_eq!(message("(f) )"), "1:5: Unexpected )");
+        assert_eq!(message("(f ')"), "1:4: Expected an expression after quote");
+        assert_eq!(message("#(1 2] 3)"), "1:6: Unexpected ]");
+        assert_eq!(message("[1 2} 3]"), "1:5: Unexpected }");
+        assert_eq!(
+            read("(f [1 2)")
+                .unwrap_err()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["1:1: Unclosed (", "1:4: Unclosed [", "1:8: Unexpected )"]
+        );
+    }
+
+    #[test]
+    fn reports_every_error_and_keeps_a_partial_tree() {
+        let source = "(define x #1)\n)\n(f \"bad \\q escape\" 'x)\n(g \"open";
+        let (data, errors) = read_partial(source);
+
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "1:11: Unexpected character '#'",
+                "2:1: Unexpected )",
+                "3:9: Unknown escape sequence \\q",
+                "4:1: Unclosed (",
+                "4:4: Unterminated string literal",
+            ]
+        );
+        assert_eq!(errors[0].span, Span { start: 10, end: 11 });
+        assert_eq!(
+            data.iter().map(ParserResult::to_source).collect::<Vec<_>>(),
+            vec![
+                "(define x 1)",
+                "(f \"bad  escape\" (quote x))",
+                "(g \"open\")"
+            ]
+        );
+    }
 }
@@ -0,0 +1,203 @@
+use std::rc::Rc;
+
+use crate::tokenizer::{escape, write_symbol};
+use crate::value::Value;
+
+/// How a value is rendered.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Style {
+    /// Readable: reading the text back gives an equal value, so strings are
+    /// quoted and escaped, and so are symbols that need it.
+    Write,
+    /// For people: strings appear as their bare contents.
+    Display,
+}
+
+/// The value as `write` prints it.
+pub fn write(value: &Value) -> String {
+    print(value, Style::Write)
+}
+
+/// The value as `display` prints it.
+pub fn display(value: &Value) -> String {
+    print(value, Style::Display)
+}
+
+pub fn print(value: &Value, style: Style) -> String {
+    let mut printer = Printer {
+        style,
+        out: String::new(),
+        path: vec![],
+    };
+    printer.print(value);
+    printer.out
+}
+
+struct Printer {
+    style: Style,
+    out: String,
+    /// The containers being printed, outermost first. Meeting one of them
+    /// again means the structure contains itself.
+    path: Vec<*const ()>,
+}
+
+impl Printer {
+    fn print(&mut self, value: &Value) {
+        match value {
+            Value::Number(number) => self.out.push_str(&number.to_string()),
+            Value::Bool(boolean) => self.out.push_str(&boolean.to_string()),
+            Value::Str(string) => match self.style {
+                Style::Write => {
+                    self.out.push('"');
+                    self.out.push_str(&escape(string));
+                    self.out.push('"');
+                }
+                Style::Display => self.out.push_str(string),
+            },
+            Value::Symbol(symbol) => match self.style {
+                Style::Write => self.out.push_str(&write_symbol(symbol.name())),
+                Style::Display => self.out.push_str(symbol.name()),
+            },
+            Value::Keyword(keyword) => {
+                self.out.push(':');
+                self.out.push_str(keyword.name());
+            }
+            Value::List(items) => self.sequence(items.as_ptr().cast(), "(", items, ")"),
+            Value::Vector(items) => {
+                self.sequence(Rc::as_ptr(items).cast(), "#(", items.borrow().iter(), ")")
+            }
+            Value::Map(map) => {
+                let entries = map.borrow();
+                let items = entries.iter().flat_map(|(key, value)| [key, value]);
+                self.sequence(Rc::as_ptr(map).cast(), "{", items, "}")
+            }
+            Value::Procedure(closure) => self
+                .out
+                .push_str(&format!("#<procedure {}>", closure.lambda.name)),
+            Value::Primitive(primitive) => self
+                .out
+                .push_str(&format!("#<primitive {}>", primitive.name)),
+            Value::Port(_) => self.out.push_str("#<port>"),
+            Value::Unspecified => self.out.push_str("#<unspecified>"),
+        }
+    }
+
+    /// Prints `items` between `open` and `close`, or `#<cycle>` when the
+    /// container they belong to, `identity`, is already being printed
+    /// further out.
+    fn sequence<'a>(
+        &mut self,
+        identity: *const (),
+        open: &str,
+        items: impl IntoIterator<Item = &'a Value>,
+        close: &str,
+    ) {
+        if self.path.contains(&identity) {
+            self.out.push_str("#<cycle>");
+            return;
+        }
+
+        self.path.push(identity);
+        self.out.push_str(open);
+        for (index, item) in items.into_iter().enumerate() {
+            if index > 0 {
+                self.out.push(' ');
+            }
+            self.print(item);
+        }
+        self.out.push_str(close);
+        self.path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::run;
+    use crate::symbol::Symbol;
+
+    fn list(items: Vec<Value>) -> Value {
+        Value::List(items)
+    }
+
+    #[test]
+    fn strings_are_quoted_only_when_written() {
+        let value = list(vec![
+            Value::Str("say \"hi\"\n".to_string()),
+            Value::symbol("hi"),
+            Value::Number(1.5),
+            Value::Bool(false),
+        ]);
+
+        assert_eq!(write(&value), r#"("say \"hi\"\n" hi 1.5 false)"#);
+        assert_eq!(display(&value), "(say \"hi\"\n hi 1.5 false)");
+    }
+
+    #[test]
+    fn nested_and_opaque_values() {
+        let value = list(vec![
+            list(vec![]),
+            list(vec![
+                Value::Str("a".to_string()),
+                list(vec![Value::Number(2.0)]),
+            ]),
+            Value::Unspecified,
+        ]);
+
+        assert_eq!(write(&value), r#"(() ("a" (2)) #<unspecified>)"#);
+    }
+
+    #[test]
+    fn vectors_and_cycles() {
+        let value = Value::vector(vec![Value::Str("a".to_string()), list(vec![])]);
+        assert_eq!(write(&value), r#"#("a" ())"#);
+        assert_eq!(display(&value), "#(a ())");
+
+        let Value::Vector(items) = &value else {
+            unreachable!()
+        };
+        items.borrow_mut()[1] = list(vec![value.clone()]);
+        assert_eq!(write(&value), r#"#("a" (#<cycle>))"#);
+    }
+
+    #[test]
+    fn maps_and_cycles() {
+        let map = Value::map(
+            [
+                (
+                    Value::Keyword(Symbol::new("a")),
+                    Value::Str("x".to_string()),
+                ),
+                (Value::Number(2.0), list(vec![])),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(write(&map), r#"{:a "x" 2 ()}"#);
+        assert_eq!(display(&map), "{:a x 2 ()}");
+
+        let Value::Map(entries) = &map else {
+            unreachable!()
+        };
+        entries
+            .borrow_mut()
+            .insert(Value::Number(2.0), Value::vector(vec![map.clone()]));
+        assert_eq!(write(&map), r#"{:a "x" 2 #(#<cycle>)}"#);
+    }
+
+    #[test]
+    fn display_write_and_newline_procedures() {
+        let source = r#"
+            (define port (open-output-string))
+            (display "a \"b\"" port)
+            (newline port)
+            (write "a \"b\"" port)
+            (write (string-split "x y") port)
+            (get-output-string port)"#;
+
+        assert_eq!(
+            run(source),
+            Ok(r#""a \"b\"\n\"a \\\"b\\\"\"(\"x\" \"y\")""#.to_string())
+        );
+    }
+}
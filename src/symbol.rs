@@ -0,0 +1,92 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+thread_local! {
+    /// Every name made into a symbol so far, on this thread.
+    static NAMES: RefCell<HashSet<Rc<str>>> = RefCell::new(HashSet::new());
+    /// How many symbols `gensym` has made, on this thread.
+    static GENSYMS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// An interned name: there is one `Symbol` per distinct name, so comparing
+/// and hashing symbols is by pointer rather than by their text.
+#[derive(Clone)]
+pub struct Symbol(Rc<str>);
+
+impl Symbol {
+    pub fn new(name: &str) -> Symbol {
+        NAMES.with(|names| {
+            let mut names = names.borrow_mut();
+            match names.get(name) {
+                Some(interned) => Symbol(interned.clone()),
+                None => {
+                    let interned: Rc<str> = Rc::from(name);
+                    names.insert(interned.clone());
+                    Symbol(interned)
+                }
+            }
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+
+    /// A symbol named `#:` followed by `prefix` and a number no other call
+    /// used, for bindings a macro introduces. The `#:` keeps it apart from
+    /// ordinary names.
+    pub fn gensym(prefix: &str) -> Symbol {
+        let count = GENSYMS.with(|count| {
+            count.set(count.get() + 1);
+            count.get()
+        });
+        Symbol::new(&format!("#:{}{}", prefix, count))
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Rc::as_ptr(&self.0).cast::<u8>().hash(state)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_interned() {
+        let a = Symbol::new("apple");
+        let b = Symbol::new(&String::from("apple"));
+
+        assert!(Rc::ptr_eq(&a.0, &b.0));
+        assert_eq!(a, b);
+        assert_ne!(a, Symbol::new("apples"));
+        assert_eq!(b.name(), "apple");
+    }
+}
@@ -0,0 +1,666 @@
+use std::{fmt, iter::Peekable, rc::Rc, vec};
+
+use crate::parser::{classify, Element, Kind, ParserResult};
+use crate::tokenizer::{lex, scan_string_recovering, scan_symbol, SyntaxError};
+use crate::tokens::{Position, Span, Token, TokenType};
+
+/// The concrete syntax tree keeps every byte of the source, whitespace and
+/// comments included, so tools can rewrite a file and print it back
+/// unchanged everywhere they did not touch.
+///
+/// It comes in two layers. Green nodes are immutable and know only their
+/// kind, width and children, so identical subtrees can be shared. Red
+/// `SyntaxNode`s are built on demand on top of them and add what depends on
+/// the position in the file: the offset and the parent.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum NodeKind {
+    /// A whole source text.
+    Root,
+    /// `( ... )`, with or without its closing parenthesis.
+    List,
+    /// `#( ... )` or `[ ... ]`, likewise.
+    Vector,
+    /// `{ ... }`, likewise.
+    Map,
+    /// `'x`, `` `x ``, `,x` or `,@x`.
+    Quoted,
+    /// `#;` and the datum it comments out.
+    DatumComment,
+    /// The tokens of a datum nested deeper than `MAX_DEPTH`, unread.
+    TooDeep,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct GreenToken {
+    pub kind: TokenType,
+    pub text: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct GreenNode {
+    pub kind: NodeKind,
+    /// Length of the node's text in bytes.
+    pub width: usize,
+    pub children: Vec<GreenElement>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenNode {
+    pub fn new(kind: NodeKind, children: Vec<GreenElement>) -> Self {
+        Self {
+            kind,
+            width: children.iter().map(GreenElement::width).sum(),
+            children,
+        }
+    }
+}
+
+impl GreenElement {
+    pub fn width(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.width,
+            GreenElement::Token(token) => token.text.len(),
+        }
+    }
+
+    fn write(&self, out: &mut String) {
+        match self {
+            GreenElement::Node(node) => node.children.iter().for_each(|child| child.write(out)),
+            GreenElement::Token(token) => out.push_str(&token.text),
+        }
+    }
+
+    /// The datum this element reads as, if any: trivia, delimiters and
+    /// commented-out data read as nothing. The element starts at byte
+    /// `offset`, which `lines` turns into the positions of its atoms.
+    fn datum(&self, offset: usize, lines: &Lines) -> Option<ParserResult> {
+        let node = match self {
+            GreenElement::Token(token) => return atom(token, lines.position(offset)),
+            GreenElement::Node(node) => node,
+        };
+
+        match node.kind {
+            NodeKind::List => Some(ParserResult::Expression(node.data(offset, lines))),
+            NodeKind::Vector => Some(ParserResult::Vector(node.data(offset, lines))),
+            NodeKind::Map => Some(ParserResult::Map(node.data(offset, lines))),
+            NodeKind::Quoted => {
+                let name = match &node.children[0] {
+                    GreenElement::Token(prefix) => quote_name(prefix.kind),
+                    GreenElement::Node(_) => unreachable!("quoted data start with a prefix"),
+                };
+                let datum = node.data(offset, lines).into_iter().next()?;
+
+                Some(ParserResult::Expression(vec![
+                    ParserResult::Atom(Element {
+                        kind: classify(name),
+                        value: name.to_string(),
+                        position: Some(lines.position(offset)),
+                    }),
+                    datum,
+                ]))
+            }
+            NodeKind::Root | NodeKind::DatumComment | NodeKind::TooDeep => None,
+        }
+    }
+}
+
+impl GreenNode {
+    /// The data among the children of the node starting at byte `offset`.
+    fn data(&self, mut offset: usize, lines: &Lines) -> Vec<ParserResult> {
+        self.children
+            .iter()
+            .filter_map(|child| {
+                let datum = child.datum(offset, lines);
+                offset += child.width();
+                datum
+            })
+            .collect()
+    }
+}
+
+/// Where the lines of a source text start, to find the position of a byte.
+#[derive(Debug)]
+struct Lines {
+    text: String,
+    starts: Vec<usize>,
+}
+
+impl Lines {
+    fn new(text: String) -> Self {
+        let starts = [0]
+            .into_iter()
+            .chain(text.match_indices('\n').map(|(newline, _)| newline + 1))
+            .collect();
+        Self { text, starts }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let line = self.starts.partition_point(|&start| start <= offset);
+        let start = self.starts[line - 1];
+
+        Position {
+            line,
+            column: self.text[start..offset].chars().count() + 1,
+        }
+    }
+}
+
+/// The symbol a quote shorthand stands for.
+fn quote_name(prefix: TokenType) -> &'static str {
+    match prefix {
+        TokenType::QUOTE => "quote",
+        TokenType::QUASIQUOTE => "quasiquote",
+        TokenType::UNQUOTESPLICING => "unquote-splicing",
+        _ => "unquote",
+    }
+}
+
+fn atom(token: &GreenToken, position: Position) -> Option<ParserResult> {
+    let (kind, value) = match token.kind {
+        TokenType::NUMBER => (Kind::Literal, token.text.clone()),
+        TokenType::STRING => (
+            Kind::String,
+            scan_string_recovering(&token.text, 0, &mut vec![]).0,
+        ),
+        // Bars make any name an ordinary symbol, even `|define|` or `|true|`
+        TokenType::IDENTIFIER if token.text.starts_with('|') && token.text.len() > 1 => {
+            (Kind::Identifier, scan_symbol(&token.text, 0, &mut vec![]).0)
+        }
+        TokenType::IDENTIFIER
+        | TokenType::TRUE
+        | TokenType::FALSE
+        | TokenType::COND
+        | TokenType::IF
+        | TokenType::ELSE
+        | TokenType::FUN
+        | TokenType::LIST => (classify(&token.text), token.text.clone()),
+        _ => return None,
+    };
+
+    Some(ParserResult::Atom(Element {
+        kind,
+        value,
+        position: Some(position),
+    }))
+}
+
+/// A green node at a place in the tree.
+#[derive(Debug, Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+#[derive(Debug)]
+struct NodeData {
+    green: Rc<GreenNode>,
+    offset: usize,
+    parent: Option<SyntaxNode>,
+    /// Those of the whole text, shared by every node in the tree.
+    lines: Rc<Lines>,
+}
+
+/// A green token at a place in the tree.
+#[derive(Debug, Clone)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    offset: usize,
+    parent: SyntaxNode,
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    pub fn root(green: Rc<GreenNode>) -> Self {
+        let mut text = String::with_capacity(green.width);
+        GreenElement::Node(green.clone()).write(&mut text);
+
+        Self(Rc::new(NodeData {
+            green,
+            offset: 0,
+            parent: None,
+            lines: Rc::new(Lines::new(text)),
+        }))
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.0.green.kind
+    }
+
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.0.green
+    }
+
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.0.offset,
+            end: self.0.offset + self.0.green.width,
+        }
+    }
+
+    pub fn parent(&self) -> Option<&SyntaxNode> {
+        self.0.parent.as_ref()
+    }
+
+    pub fn children(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+
+        self.0
+            .green
+            .children
+            .iter()
+            .map(|child| {
+                let element = match child {
+                    GreenElement::Node(green) => SyntaxElement::Node(Self(Rc::new(NodeData {
+                        green: green.clone(),
+                        offset,
+                        parent: Some(self.clone()),
+                        lines: self.0.lines.clone(),
+                    }))),
+                    GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                        green: green.clone(),
+                        offset,
+                        parent: self.clone(),
+                    }),
+                };
+                offset += child.width();
+                element
+            })
+            .collect()
+    }
+
+    /// The node's source text, exactly as it was read.
+    pub fn text(&self) -> String {
+        let mut text = String::with_capacity(self.0.green.width);
+        GreenElement::Node(self.0.green.clone()).write(&mut text);
+        text
+    }
+
+    /// The data among the node's children, as the reader produces them.
+    pub fn data(&self) -> Vec<ParserResult> {
+        self.0.green.data(self.0.offset, &self.0.lines)
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+impl SyntaxElement {
+    pub fn span(&self) -> Span {
+        match self {
+            SyntaxElement::Node(node) => node.span(),
+            SyntaxElement::Token(token) => token.span(),
+        }
+    }
+
+    /// The datum this element reads as, if any.
+    pub fn datum(&self) -> Option<ParserResult> {
+        match self {
+            SyntaxElement::Node(node) => {
+                GreenElement::Node(node.green().clone()).datum(node.0.offset, &node.0.lines)
+            }
+            SyntaxElement::Token(token) => {
+                atom(&token.green, token.parent.0.lines.position(token.offset))
+            }
+        }
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> TokenType {
+        self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.offset,
+            end: self.offset + self.green.text.len(),
+        }
+    }
+
+    pub fn parent(&self) -> &SyntaxNode {
+        &self.parent
+    }
+}
+
+/// How deeply lists, vectors, maps and prefixes like `'` may nest. Whatever
+/// walks the tree or the data read from it recurses, so deeper text would
+/// exhaust the native stack; past this it is a syntax error instead.
+pub const MAX_DEPTH: usize = 256;
+
+/// Builds the syntax tree of `source`. Malformed text still ends up in the
+/// tree, so it always prints back as `source`; the problems are returned
+/// alongside it, in source order.
+pub fn parse(source: &str) -> (SyntaxNode, Vec<SyntaxError>) {
+    let (tokens, errors) = lex(source);
+    let mut builder = Builder {
+        source,
+        tokens: tokens.into_iter().peekable(),
+        errors,
+        depth: 0,
+    };
+
+    let mut children = vec![];
+    loop {
+        builder.trivia(&mut children);
+
+        match builder.peek() {
+            None => break,
+            Some(token_type) if token_type.is_close() => children.push(builder.unexpected()),
+            Some(_) => children.push(builder.datum()),
+        }
+    }
+
+    let mut errors = builder.errors;
+    errors.sort_by_key(|error| error.span.start);
+
+    let root = SyntaxNode::root(Rc::new(GreenNode::new(NodeKind::Root, children)));
+    (root, errors)
+}
+
+struct Builder<'a> {
+    source: &'a str,
+    tokens: Peekable<vec::IntoIter<Token>>,
+    errors: Vec<SyntaxError>,
+    /// How many sequences and prefixes enclose the next token.
+    depth: usize,
+}
+
+impl Builder<'_> {
+    fn peek(&mut self) -> Option<TokenType> {
+        self.tokens.peek().map(|token| token.token_type)
+    }
+
+    fn bump_token(&mut self) -> Token {
+        self.tokens.next().expect("bumped past the last token")
+    }
+
+    fn bump(&mut self) -> GreenElement {
+        green_token(self.bump_token())
+    }
+
+    /// Moves trivia and datum comments into `children`.
+    fn trivia(&mut self, children: &mut Vec<GreenElement>) {
+        loop {
+            match self.peek() {
+                Some(token_type) if token_type.is_trivia() => children.push(self.bump()),
+                Some(TokenType::DATUMCOMMENT) => {
+                    let prefix = self.bump_token();
+                    children.push(self.prefixed(prefix, NodeKind::DatumComment));
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// The datum at the next token, which is neither trivia nor a closing
+    /// delimiter.
+    fn datum(&mut self) -> GreenElement {
+        let token = self.bump_token();
+
+        match token.token_type {
+            token_type if token_type.is_open() => self.sequence(token),
+            TokenType::QUOTE
+            | TokenType::QUASIQUOTE
+            | TokenType::UNQUOTE
+            | TokenType::UNQUOTESPLICING => self.prefixed(token, NodeKind::Quoted),
+            _ => green_token(token),
+        }
+    }
+
+    /// A list, vector or map, up to the delimiter that closes it. Closing
+    /// delimiters of the wrong sort are skipped.
+    fn sequence(&mut self, open: Token) -> GreenElement {
+        if self.depth == MAX_DEPTH {
+            return self.too_deep(open);
+        }
+
+        let kind = match open.token_type {
+            TokenType::LEFTPAREN => NodeKind::List,
+            TokenType::LEFTBRACE => NodeKind::Map,
+            _ => NodeKind::Vector,
+        };
+        let closer = open.token_type.closer();
+        let (span, unclosed) = (open.span, format!("Unclosed {}", open.lexeme));
+        let mut children = vec![green_token(open)];
+        let mut data = 0;
+        self.depth += 1;
+
+        loop {
+            self.trivia(&mut children);
+
+            match self.peek() {
+                None => {
+                    self.error(span, &unclosed);
+                    break;
+                }
+                Some(token_type) if token_type == closer => {
+                    children.push(self.bump());
+                    break;
+                }
+                Some(token_type) if token_type.is_close() => children.push(self.unexpected()),
+                Some(_) => {
+                    children.push(self.datum());
+                    data += 1;
+                }
+            }
+        }
+
+        self.depth -= 1;
+        if kind == NodeKind::Map && data % 2 != 0 {
+            self.error(span, "Expected a value for every key in {");
+        }
+
+        node(kind, children)
+    }
+
+    /// A closing delimiter with nothing to close.
+    fn unexpected(&mut self) -> GreenElement {
+        let token = self.bump_token();
+        self.error(token.span, &format!("Unexpected {}", token.lexeme));
+        green_token(token)
+    }
+
+    /// A quote shorthand or `#;`, and the datum that follows it.
+    fn prefixed(&mut self, prefix: Token, kind: NodeKind) -> GreenElement {
+        if self.depth == MAX_DEPTH {
+            return self.too_deep(prefix);
+        }
+
+        let (span, prefix_type) = (prefix.span, prefix.token_type);
+        let mut children = vec![green_token(prefix)];
+        self.depth += 1;
+        self.trivia(&mut children);
+
+        match self.peek() {
+            Some(token_type) if !token_type.is_close() => children.push(self.datum()),
+            _ => {
+                let message = match kind {
+                    NodeKind::DatumComment => "Expected a datum after #;".to_string(),
+                    _ => format!("Expected an expression after {}", quote_name(prefix_type)),
+                };
+                self.error(span, &message);
+            }
+        }
+
+        self.depth -= 1;
+        node(kind, children)
+    }
+
+    /// The rest of a datum that starts at `first` but nests too deeply,
+    /// taken token by token: up to the delimiter that balances `first`, or
+    /// through the datum after a run of prefixes.
+    fn too_deep(&mut self, first: Token) -> GreenElement {
+        self.error(
+            first.span,
+            &format!("Nested more than {} levels deep", MAX_DEPTH),
+        );
+
+        let mut children = vec![];
+        let (mut open, mut token) = (0, first);
+        loop {
+            let token_type = token.token_type;
+            children.push(green_token(token));
+
+            if token_type.is_open() {
+                open += 1;
+            } else if token_type.is_close() {
+                open -= 1;
+            }
+            if open == 0 && !is_prefix(token_type) {
+                break;
+            }
+
+            while self.peek().is_some_and(TokenType::is_trivia) {
+                children.push(self.bump());
+            }
+            match self.peek() {
+                Some(token_type) if !(open == 0 && token_type.is_close()) => {
+                    token = self.bump_token()
+                }
+                _ => break,
+            }
+        }
+
+        node(NodeKind::TooDeep, children)
+    }
+
+    fn error(&mut self, span: Span, message: &str) {
+        self.errors
+            .push(SyntaxError::at(self.source, span, message.to_string()));
+    }
+}
+
+/// Whether a datum follows the token: a quote shorthand or `#;`.
+fn is_prefix(token_type: TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::QUOTE
+            | TokenType::QUASIQUOTE
+            | TokenType::UNQUOTE
+            | TokenType::UNQUOTESPLICING
+            | TokenType::DATUMCOMMENT
+    )
+}
+
+fn green_token(token: Token) -> GreenElement {
+    GreenElement::Token(Rc::new(GreenToken {
+        kind: token.token_type,
+        text: token.lexeme,
+    }))
+}
+
+fn node(kind: NodeKind, children: Vec<GreenElement>) -> GreenElement {
+    GreenElement::Node(Rc::new(GreenNode::new(kind, children)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::read;
+
+    const SOURCE: &str =
+        "; header\n(define (f x)   #| block |#\n  '(a . b) #;(skipped)\n\t`(,x ,@y)) ; done\n";
+
+    #[test]
+    fn prints_back_byte_for_byte() {
+        for source in [
+            SOURCE,
+            "",
+            "  \n",
+            "(unclosed \"str",
+            ") stray [chars] #| open",
+            "(λ \"héllo\" #r#\"raw\"#)",
+            "[a #(b] c) ]",
+        ] {
+            assert_eq!(parse(source).0.text(), source);
+        }
+    }
+
+    #[test]
+    fn derives_the_reader_data() {
+        let (tree, errors) = parse(SOURCE);
+
+        assert!(errors.is_empty());
+        assert_eq!(tree.data(), read(SOURCE).unwrap());
+        assert_eq!(
+            tree.data()[0].to_source(),
+            "(define (f x) (quote (a . b)) (quasiquote ((unquote x) (unquote-splicing y))))"
+        );
+    }
+
+    #[test]
+    fn red_nodes_know_their_place() {
+        let (tree, _) = parse("(a #;b (c))");
+        let list = match &tree.children()[0] {
+            SyntaxElement::Node(node) => node.clone(),
+            SyntaxElement::Token(_) => unreachable!(),
+        };
+        let children = list.children();
+
+        assert_eq!(list.kind(), NodeKind::List);
+        assert_eq!(list.parent().map(SyntaxNode::kind), Some(NodeKind::Root));
+        assert_eq!(
+            children
+                .iter()
+                .map(|child| match child {
+                    SyntaxElement::Node(node) => (format!("{:?}", node.kind()), node.span()),
+                    SyntaxElement::Token(token) => (format!("{:?}", token.kind()), token.span()),
+                })
+                .collect::<Vec<_>>(),
+            vec![
+                ("LEFTPAREN".to_string(), Span { start: 0, end: 1 }),
+                ("IDENTIFIER".to_string(), Span { start: 1, end: 2 }),
+                ("WHITESPACE".to_string(), Span { start: 2, end: 3 }),
+                ("DatumComment".to_string(), Span { start: 3, end: 6 }),
+                ("WHITESPACE".to_string(), Span { start: 6, end: 7 }),
+                ("List".to_string(), Span { start: 7, end: 10 }),
+                ("RIGHTPAREN".to_string(), Span { start: 10, end: 11 }),
+            ]
+        );
+        match &children[5] {
+            SyntaxElement::Node(inner) => assert_eq!(inner.text(), "(c)"),
+            SyntaxElement::Token(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let deep = |depth| format!("{}x{}", "([".repeat(depth), "])".repeat(depth));
+        let source = format!("{} '{}", deep(MAX_DEPTH), deep(MAX_DEPTH / 2));
+        let (tree, errors) = parse(&source);
+
+        assert_eq!(tree.text(), source);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                format!(
+                    "1:{}: Nested more than {} levels deep",
+                    MAX_DEPTH + 1,
+                    MAX_DEPTH
+                ),
+                // After the first datum, ` '` and the delimiters the quote leaves room for
+                format!(
+                    "1:{}: Nested more than {} levels deep",
+                    (4 * MAX_DEPTH + 1) + 2 + MAX_DEPTH,
+                    MAX_DEPTH
+                ),
+            ]
+        );
+        assert_eq!(parse(&deep(MAX_DEPTH / 2)).1, vec![]);
+        assert_eq!(parse(&format!("{}x", "'".repeat(MAX_DEPTH))).1, vec![]);
+    }
+}
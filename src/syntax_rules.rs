@@ -0,0 +1,490 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::interpreter::InterpretError;
+use crate::parser::*;
+
+/// A `syntax-rules` transformer: a list of (pattern template) rules tried in
+/// order against the macro call.
+#[derive(Clone, Debug)]
+pub struct SyntaxRules {
+    ellipsis: String,
+    literals: Vec<String>,
+    rules: Vec<(ParserResult, ParserResult)>,
+}
+
+/// What a pattern variable matched; variables under an ellipsis match one
+/// capture per repetition.
+#[derive(Clone, Debug)]
+enum Capture {
+    One(ParserResult),
+    Many(Vec<Capture>),
+}
+
+type Captures = HashMap<String, Capture>;
+
+impl SyntaxRules {
+    /// Builds the transformer from the items of a `(syntax-rules ...)` form,
+    /// `syntax-rules` itself excluded.
+    pub fn parse(spec: &[ParserResult]) -> Result<Self, InterpretError> {
+        // R7RS lets the ellipsis be renamed: (syntax-rules ::: (literals) rules...)
+        let (ellipsis, spec) = match spec.first() {
+            Some(ParserResult::Atom(custom)) => (custom.value.clone(), &spec[1..]),
+            _ => ("...".to_string(), spec),
+        };
+
+        let literals = match spec.first() {
+            Some(ParserResult::Expression(literals)) => {
+                literals.iter().map(|literal| literal.to_string()).collect()
+            }
+            _ => {
+                return Err(InterpretError::Expected(
+                    "Expected a literals list in syntax-rules".to_string(),
+                ))
+            }
+        };
+
+        let rules = spec[1..]
+            .iter()
+            .map(|rule| match rule {
+                ParserResult::Expression(rule) if rule.len() == 2 => match &rule[0] {
+                    ParserResult::Expression(_) => Ok((rule[0].clone(), rule[1].clone())),
+                    _ => Err(InterpretError::Expected(
+                        "A syntax-rules pattern must be a list".to_string(),
+                    )),
+                },
+                _ => Err(InterpretError::Expected(
+                    "Expected (pattern template) in syntax-rules".to_string(),
+                )),
+            })
+            .collect::<Result<Vec<_>, InterpretError>>()?;
+
+        Ok(Self {
+            ellipsis,
+            literals,
+            rules,
+        })
+    }
+
+    /// Rewrites a macro call with the first matching rule. Bindings the
+    /// template introduces itself are renamed through `gensym` so they cannot
+    /// capture names used in the macro arguments.
+    pub fn expand(
+        &self,
+        form: &ParserResult,
+        gensym: &mut dyn FnMut(&str) -> String,
+    ) -> Result<ParserResult, InterpretError> {
+        let args = match form {
+            ParserResult::Expression(items) => &items[1..],
+            _ => &[],
+        };
+
+        for (pattern, template) in &self.rules {
+            let mut captures = Captures::new();
+
+            // The keyword position of the pattern is ignored
+            let pattern = match pattern {
+                ParserResult::Expression(items) => &items[1..],
+                _ => &[],
+            };
+
+            if self.match_list(pattern, args, &mut captures) {
+                let mut renames = HashMap::new();
+                for name in self.introduced_binders(template, &captures) {
+                    let fresh = gensym(&name);
+                    renames.insert(name, fresh);
+                }
+
+                return self.instantiate(template, &captures, &renames);
+            }
+        }
+
+        Err(InterpretError::Expected(format!(
+            "No syntax-rules pattern matches {}",
+            form.to_source()
+        )))
+    }
+
+    fn match_pattern(
+        &self,
+        pattern: &ParserResult,
+        form: &ParserResult,
+        captures: &mut Captures,
+    ) -> bool {
+        match (pattern, form) {
+            (ParserResult::Atom(p), _) if p.kind == Kind::Identifier => {
+                if self.literals.contains(&p.value) {
+                    matches!(form, ParserResult::Atom(f) if f.value == p.value)
+                } else {
+                    if p.value != "_" {
+                        captures.insert(p.value.clone(), Capture::One(form.clone()));
+                    }
+                    true
+                }
+            }
+            (ParserResult::Atom(p), ParserResult::Atom(f)) => p.value == f.value,
+            (ParserResult::Expression(p), ParserResult::Expression(f))
+            | (ParserResult::Vector(p), ParserResult::Vector(f))
+            | (ParserResult::Map(p), ParserResult::Map(f)) => self.match_list(p, f, captures),
+            _ => false,
+        }
+    }
+
+    fn match_list(
+        &self,
+        patterns: &[ParserResult],
+        forms: &[ParserResult],
+        captures: &mut Captures,
+    ) -> bool {
+        // Dotted tail: (a b . rest) binds rest to the remaining forms
+        if let Some(dot) = patterns.iter().position(|p| is_atom(p, ".")) {
+            if dot + 2 != patterns.len() || forms.len() < dot {
+                return false;
+            }
+            let rest = ParserResult::Expression(forms[dot..].to_vec());
+            return self.match_list(&patterns[..dot], &forms[..dot], captures)
+                && self.match_pattern(&patterns[dot + 1], &rest, captures);
+        }
+
+        // Index of the pattern the ellipsis repeats
+        let repeated_at = patterns
+            .iter()
+            .skip(1)
+            .position(|p| is_atom(p, &self.ellipsis));
+
+        let Some(repeated_at) = repeated_at else {
+            return patterns.len() == forms.len()
+                && patterns
+                    .iter()
+                    .zip(forms)
+                    .all(|(pattern, form)| self.match_pattern(pattern, form, captures));
+        };
+
+        let before = &patterns[..repeated_at];
+        let repeated = &patterns[repeated_at];
+        let after = &patterns[repeated_at + 2..];
+
+        if forms.len() < before.len() + after.len() {
+            return false;
+        }
+
+        let middle_end = forms.len() - after.len();
+
+        if !before
+            .iter()
+            .zip(&forms[..before.len()])
+            .all(|(pattern, form)| self.match_pattern(pattern, form, captures))
+        {
+            return false;
+        }
+
+        if !after
+            .iter()
+            .zip(&forms[middle_end..])
+            .all(|(pattern, form)| self.match_pattern(pattern, form, captures))
+        {
+            return false;
+        }
+
+        let mut repetitions: Vec<Captures> = vec![];
+        for form in &forms[before.len()..middle_end] {
+            let mut repetition = Captures::new();
+            if !self.match_pattern(repeated, form, &mut repetition) {
+                return false;
+            }
+            repetitions.push(repetition);
+        }
+
+        for name in self.pattern_variables(repeated) {
+            let matched = repetitions
+                .iter_mut()
+                .map(|repetition| repetition.remove(&name).unwrap())
+                .collect();
+            captures.insert(name, Capture::Many(matched));
+        }
+
+        true
+    }
+
+    fn pattern_variables(&self, pattern: &ParserResult) -> Vec<String> {
+        match pattern {
+            ParserResult::Atom(p)
+                if p.kind == Kind::Identifier
+                    && p.value != "_"
+                    && p.value != "."
+                    && p.value != self.ellipsis
+                    && !self.literals.contains(&p.value) =>
+            {
+                vec![p.value.clone()]
+            }
+            ParserResult::Atom(_) => vec![],
+            ParserResult::Expression(items)
+            | ParserResult::Vector(items)
+            | ParserResult::Map(items) => items
+                .iter()
+                .flat_map(|item| self.pattern_variables(item))
+                .collect(),
+        }
+    }
+
+    fn instantiate(
+        &self,
+        template: &ParserResult,
+        captures: &Captures,
+        renames: &HashMap<String, String>,
+    ) -> Result<ParserResult, InterpretError> {
+        let items = match template {
+            ParserResult::Atom(element) => {
+                return match captures.get(&element.value) {
+                    Some(Capture::One(form)) => Ok(form.clone()),
+                    Some(Capture::Many(_)) => Err(InterpretError::Expected(format!(
+                        "Pattern variable {} is used without {}",
+                        element.value, self.ellipsis
+                    ))),
+                    None => match renames.get(&element.value) {
+                        Some(fresh) => Ok(ParserResult::Atom(Element {
+                            kind: Kind::Identifier,
+                            value: fresh.clone(),
+                            position: None,
+                        })),
+                        // The expander places what the template writes at the
+                        // macro call, not in the definition
+                        None => Ok(ParserResult::Atom(Element {
+                            position: None,
+                            ..element.clone()
+                        })),
+                    },
+                };
+            }
+            ParserResult::Expression(items) => items,
+            ParserResult::Vector(items) => {
+                let list = ParserResult::Expression(items.clone());
+                return match self.instantiate(&list, captures, renames)? {
+                    ParserResult::Expression(items) => Ok(ParserResult::Vector(items)),
+                    other => Ok(other),
+                };
+            }
+            ParserResult::Map(items) => {
+                let list = ParserResult::Expression(items.clone());
+                return match self.instantiate(&list, captures, renames)? {
+                    ParserResult::Expression(items) => Ok(ParserResult::Map(items)),
+                    other => Ok(other),
+                };
+            }
+        };
+
+        // (... ...) escapes the ellipsis so templates can produce it literally
+        if items.len() == 2 && is_atom(&items[0], &self.ellipsis) {
+            return Ok(items[1].clone());
+        }
+
+        let mut result = vec![];
+        let mut index = 0;
+
+        while index < items.len() {
+            let item = &items[index];
+
+            if items
+                .get(index + 1)
+                .is_some_and(|next| is_atom(next, &self.ellipsis))
+            {
+                for repetition in self.repetitions(item, captures)? {
+                    result.push(self.instantiate(item, &repetition, renames)?);
+                }
+                index += 2;
+            } else {
+                result.push(self.instantiate(item, captures, renames)?);
+                index += 1;
+            }
+        }
+
+        Ok(ParserResult::Expression(result))
+    }
+
+    /// One set of captures per repetition of `template` under an ellipsis.
+    fn repetitions(
+        &self,
+        template: &ParserResult,
+        captures: &Captures,
+    ) -> Result<Vec<Captures>, InterpretError> {
+        let repeated: Vec<(&String, &Vec<Capture>)> = self
+            .template_symbols(template)
+            .into_iter()
+            .filter_map(|name| match captures.get_key_value(&name) {
+                Some((name, Capture::Many(matched))) => Some((name, matched)),
+                _ => None,
+            })
+            .collect();
+
+        let count = match repeated.first() {
+            Some((_, matched)) => matched.len(),
+            None => {
+                return Err(InterpretError::Expected(format!(
+                    "No pattern variable to repeat before {} in {}",
+                    self.ellipsis,
+                    template.to_source()
+                )))
+            }
+        };
+
+        if repeated.iter().any(|(_, matched)| matched.len() != count) {
+            return Err(InterpretError::Expected(format!(
+                "Pattern variables repeated by {} matched different lengths",
+                self.ellipsis
+            )));
+        }
+
+        Ok((0..count)
+            .map(|i| {
+                let mut repetition = captures.clone();
+                for (name, matched) in &repeated {
+                    repetition.insert((*name).clone(), matched[i].clone());
+                }
+                repetition
+            })
+            .collect())
+    }
+
+    fn template_symbols(&self, template: &ParserResult) -> HashSet<String> {
+        match template {
+            ParserResult::Atom(element) => HashSet::from([element.value.clone()]),
+            ParserResult::Expression(items)
+            | ParserResult::Vector(items)
+            | ParserResult::Map(items) => items
+                .iter()
+                .flat_map(|item| self.template_symbols(item))
+                .collect(),
+        }
+    }
+
+    /// Names the template binds itself, in `let` bindings or the parameter
+    /// list of a `define`, rather than taking from the macro call.
+    fn introduced_binders(&self, template: &ParserResult, captures: &Captures) -> HashSet<String> {
+        let mut binders = HashSet::new();
+
+        let items = match template {
+            ParserResult::Expression(items) => items,
+            _ => return binders,
+        };
+
+        let mut introduce = |candidate: &ParserResult| {
+            if let ParserResult::Atom(element) = candidate {
+                if element.kind == Kind::Identifier
+                    && element.value != self.ellipsis
+                    && element.value != "."
+                    && !captures.contains_key(&element.value)
+                {
+                    binders.insert(element.value.clone());
+                }
+            }
+        };
+
+        match (items.first(), items.get(1), items.get(2)) {
+            (Some(head), Some(ParserResult::Expression(bindings)), _) if is_atom(head, "let") => {
+                for binding in bindings {
+                    if let ParserResult::Expression(pair) = binding {
+                        if let Some(name) = pair.first() {
+                            introduce(name);
+                        }
+                    }
+                }
+            }
+            (Some(head), Some(_), Some(ParserResult::Expression(params)))
+                if is_atom(head, "define") && items.len() > 3 =>
+            {
+                params.iter().for_each(&mut introduce);
+            }
+            (Some(head), Some(ParserResult::Expression(params)), _) if is_atom(head, "lambda") => {
+                params.iter().for_each(&mut introduce);
+            }
+            _ => {}
+        }
+
+        for item in items {
+            binders.extend(self.introduced_binders(item, captures));
+        }
+
+        binders
+    }
+}
+
+fn is_atom(form: &ParserResult, value: &str) -> bool {
+    matches!(form, ParserResult::Atom(element) if element.value == value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::{run, InterpretError};
+
+    const MY_OR: &str = "
+        (define-syntax my-or
+            (syntax-rules ()
+                ((_) false)
+                ((_ e) e)
+                ((_ e rest ...)
+                    (let ((t e)) (if t t (my-or rest ...))))))";
+
+    #[test]
+    fn recursive_rules_with_ellipsis() {
+        let source = format!("{} (my-or false false true) (my-or)", MY_OR);
+
+        assert_eq!(run(&source), Ok("true false".to_string()));
+    }
+
+    #[test]
+    fn introduced_bindings_do_not_capture_arguments() {
+        let source = format!("{} (define t 5) (my-or false (< t 10))", MY_OR);
+
+        assert_eq!(run(&source), Ok("true".to_string()));
+    }
+
+    #[test]
+    fn or_does_not_capture_template_bindings() {
+        let source = "
+            (define-syntax pick
+                (syntax-rules () ((_ a) (let ((t a)) (or false t)))))
+            (define-syntax first-true
+                (syntax-rules () ((_ a) (let ((k a)) (cond (false) (k))))))
+            (pick 42) (first-true 7)";
+
+        assert_eq!(run(source), Ok("42 7".to_string()));
+    }
+
+    #[test]
+    fn literals_must_match_exactly() {
+        let source = "
+            (define-syntax combine
+                (syntax-rules (with)
+                    ((_ a with b) (+ a b))
+                    ((_ a b) (* a b))))
+            (combine 2 with 3)
+            (combine 2 3)";
+
+        assert_eq!(run(source), Ok("5 6".to_string()));
+    }
+
+    #[test]
+    fn nested_ellipsis_patterns() {
+        let source = "
+            (define-syntax my-let
+                (syntax-rules ()
+                    ((_ ((name value) ...) body ...)
+                        (let ((name value) ...) body ...))))
+            (my-let ((a 1) (b 2)) (define c 3) (+ a (+ b c)))";
+
+        assert_eq!(run(source), Ok("6".to_string()));
+    }
+
+    #[test]
+    fn reports_calls_no_rule_matches() {
+        let source = "
+            (define-syntax pair-only (syntax-rules () ((_ a b) (+ a b))))
+            (pair-only 1)";
+
+        assert_eq!(
+            run(source),
+            Err(InterpretError::Expected(
+                "No syntax-rules pattern matches (pair-only 1)".to_string()
+            ))
+        );
+    }
+}
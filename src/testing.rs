@@ -0,0 +1,298 @@
+use std::slice;
+
+use crate::interpreter::{Environment, Interpret, InterpretError, Interpreter};
+use crate::macros::Expander;
+use crate::parser::{Kind, ParserResult};
+use crate::syntax::{self, SyntaxElement, SyntaxNode};
+use crate::tokenizer::line_and_column;
+use crate::tokens::Span;
+
+/// A `deftest` and how running it went.
+#[derive(Debug, PartialEq)]
+pub struct TestResult {
+    pub name: String,
+    /// The 1-based line and column where the `deftest` starts.
+    pub line: usize,
+    pub column: usize,
+    pub failure: Option<Failure>,
+}
+
+/// The first form of a test's body that raised an error, such as a failed
+/// assertion, and the error.
+#[derive(Debug, PartialEq)]
+pub struct Failure {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/// Runs each `deftest` in `source` in order. Every test runs in a fresh
+/// load of the rest of the program, so nothing one test defines or changes
+/// is seen by the others. The top-level forms therefore run once per test
+/// (and once when there are none), side effects like `display` included.
+/// Returns `line:column: message` if the program fails to load.
+pub fn run_tests(source: &str) -> Result<Vec<TestResult>, String> {
+    let (tree, errors) = syntax::parse(source);
+    if !errors.is_empty() {
+        return Err(errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n"));
+    }
+
+    let (tests, program): (Vec<_>, Vec<_>) = data(&tree)
+        .into_iter()
+        .partition(|(element, form)| matches!(element, SyntaxElement::Node(_)) && is_deftest(form));
+
+    // Loaded up front, so load errors are reported even without tests
+    let mut loaded = Some(load(source, &program)?);
+    tests
+        .iter()
+        .map(|(element, _)| {
+            let SyntaxElement::Node(test) = element else {
+                unreachable!("tests are nodes")
+            };
+            let (environment, mut expander) = match loaded.take() {
+                Some(loaded) => loaded,
+                None => load(source, &program)?,
+            };
+            run_test(source, test, &environment, &mut expander)
+        })
+        .collect()
+}
+
+/// Evaluates the `program` forms in a new environment.
+fn load(
+    source: &str,
+    program: &[(SyntaxElement, ParserResult)],
+) -> Result<(Environment, Expander), String> {
+    let environment = Environment::new();
+    let mut expander = Expander::new();
+
+    for (element, form) in program {
+        eval(form, &environment, &mut expander)
+            .map_err(|error| located(source, element.span(), error))?;
+    }
+    Ok((environment, expander))
+}
+
+/// Runs the body of `test` until a form raises an error.
+fn run_test(
+    source: &str,
+    test: &SyntaxNode,
+    environment: &Environment,
+    expander: &mut Expander,
+) -> Result<TestResult, String> {
+    let items = data(test);
+    let name = match items.get(1) {
+        Some((_, ParserResult::Atom(name))) if name.kind == Kind::Identifier => name.value.clone(),
+        _ => {
+            return Err(located(
+                source,
+                test.span(),
+                InterpretError::Expected("Expected (deftest name body...)".to_string()),
+            ))
+        }
+    };
+
+    let failure = items[2..].iter().find_map(|(element, form)| {
+        let error = eval(form, environment, expander).err()?;
+        let (line, column) = line_and_column(source, element.span().start);
+
+        Some(Failure {
+            line,
+            column,
+            message: error.to_string(),
+        })
+    });
+
+    let (line, column) = line_and_column(source, test.span().start);
+    Ok(TestResult {
+        name,
+        line,
+        column,
+        failure,
+    })
+}
+
+/// The node's children that read as data, with the data.
+fn data(node: &SyntaxNode) -> Vec<(SyntaxElement, ParserResult)> {
+    node.children()
+        .into_iter()
+        .filter_map(|child| {
+            let datum = child.datum()?;
+            Some((child, datum))
+        })
+        .collect()
+}
+
+fn is_deftest(form: &ParserResult) -> bool {
+    match form {
+        ParserResult::Expression(items) => {
+            matches!(items.first(), Some(ParserResult::Atom(head)) if head.kind == Kind::Deftest)
+        }
+        _ => false,
+    }
+}
+
+fn located(source: &str, span: Span, error: InterpretError) -> String {
+    let (line, column) = line_and_column(source, span.start);
+    format!("{}:{}: {}", line, column, error)
+}
+
+/// Expands and evaluates `form`.
+fn eval(
+    form: &ParserResult,
+    environment: &Environment,
+    expander: &mut Expander,
+) -> Result<(), InterpretError> {
+    let program = expander.expand_program(slice::from_ref(form))?;
+    let mut interpreter = Interpreter::new(&program, environment);
+
+    for _ in &program {
+        interpreter.interpret_expression()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbol::Symbol;
+
+    fn failure(line: usize, column: usize, message: &str) -> Option<Failure> {
+        Some(Failure {
+            line,
+            column,
+            message: message.to_string(),
+        })
+    }
+
+    #[test]
+    fn reports_each_test_with_its_location() {
+        let source = r#"(define square (n) (* n n))
+
+(deftest squares
+  (assert-equal 9 (square 3))
+  (assert-equal 4 (square -2)))
+
+(deftest broken
+  (assert-equal 1 1)
+  (assert-equal 4 (square 3))
+  (display "not reached"))
+
+(deftest errors
+  (assert-error (square 1 2))
+  (assert-error (undefined-procedure))
+  (assert-error (square 2)))"#;
+
+        assert_eq!(
+            run_tests(source),
+            Ok(vec![
+                TestResult {
+                    name: "squares".to_string(),
+                    line: 3,
+                    column: 1,
+                    failure: None,
+                },
+                TestResult {
+                    name: "broken".to_string(),
+                    line: 7,
+                    column: 1,
+                    failure: failure(9, 3, "Expected 4, got 9"),
+                },
+                TestResult {
+                    name: "errors".to_string(),
+                    line: 12,
+                    column: 1,
+                    failure: failure(15, 3, "Expected an error, got 4"),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn tests_run_in_scopes_of_their_own() {
+        let source = "
+            (define x 1)
+            (deftest first (define x 2) (define y 3) (assert-equal 2 x))
+            (deftest second (assert-equal 1 x) (assert-error (y)))
+            (define-syntax twice (syntax-rules () ((_ e) (begin e e))))
+            (deftest macros (define n 0) (twice (set! n (+ n 1))) (assert-equal 2 n))";
+
+        let results = run_tests(source).unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(
+            results.iter().all(|result| result.failure.is_none()),
+            "{:?}",
+            results
+        );
+    }
+
+    #[test]
+    fn runtime_errors_fail_only_their_test() {
+        let results = run_tests(
+            "(deftest divides (/ 1 0)) (deftest after (assert-error (/ 2 0)) (assert-equal 2 (/ 4 2)))",
+        )
+        .unwrap();
+
+        assert_eq!(results[0].failure, failure(1, 18, "Division by zero"));
+        assert_eq!(results[1].failure, None);
+    }
+
+    #[test]
+    fn changes_to_the_program_do_not_leak_between_tests() {
+        let source = "
+            (define counter 0)
+            (define items (vector 1 2))
+            (deftest first (set! counter 10) (vector-set! items 0 'changed))
+            (deftest second (assert-equal 0 counter) (assert-equal [1 2] items))";
+
+        let results = run_tests(source).unwrap();
+        assert!(
+            results.iter().all(|result| result.failure.is_none()),
+            "{:?}",
+            results
+        );
+    }
+
+    #[test]
+    fn the_program_is_loaded_once_for_every_test() {
+        // Each load makes one gensym, so the thread's counter counts them
+        let count = || Symbol::gensym("").name()[2..].parse::<usize>().unwrap();
+        let loads = |source| {
+            let before = count();
+            run_tests(source).unwrap();
+            count() - before - 1
+        };
+
+        assert_eq!(
+            loads("(define g (gensym)) (deftest a) (deftest b) (deftest c)"),
+            3
+        );
+        assert_eq!(loads("(define g (gensym))"), 1);
+    }
+
+    #[test]
+    fn load_errors_are_located() {
+        assert_eq!(
+            run_tests("(define x 1)\n  (x 2)"),
+            Err("2:3: 1 is not a procedure".to_string())
+        );
+        assert_eq!(
+            run_tests("(deftest \"name\" 1)"),
+            Err("1:1: Expected (deftest name body...)".to_string())
+        );
+        assert_eq!(run_tests("(deftest"), Err("1:1: Unclosed (".to_string()));
+    }
+
+    #[test]
+    fn tests_are_skipped_outside_the_runner() {
+        let program = crate::parser::read("(deftest never (assert-equal 1 2)) 5").unwrap();
+
+        let env = Environment::new();
+        let mut interpreter = Interpreter::new(&program, &env);
+        assert_eq!(interpreter.get_result(), "5");
+    }
+}
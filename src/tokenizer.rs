@@ -1,240 +1,506 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
-use crate::tokens::{self, Literal, Token, TokenType};
-
-pub fn tokenize(expression: &str) -> Vec<Token> {
-    let end = expression.len();
-    let mut start = 0;
-    let mut current = 0;
+use crate::tokens::{self, Literal, Span, Token, TokenType};
 
+/// Splits `expression` into tokens without losing a byte: whitespace,
+/// comments, `#;` and stray characters come out as tokens too, so the spans
+/// tile the whole input in order. Returns the malformed strings, block
+/// comments and characters it met along the way.
+///
+/// Positions are byte offsets that always fall on `char` boundaries, so
+/// multi-byte UTF-8 input passes through intact. `;` comments run to the end
+/// of the line and `#| ... |#` blocks may nest.
+pub fn lex(expression: &str) -> (Vec<Token>, Vec<SyntaxError>) {
     let keywords: HashMap<&str, TokenType> = get_keywords();
 
     let mut tokens: Vec<Token> = vec![];
+    let mut errors: Vec<SyntaxError> = vec![];
+    let mut start = 0;
 
-    while start < end {
-        start = current;
-
-        let c: char = expression.as_bytes()[start] as char;
+    while let Some(c) = expression[start..].chars().next() {
+        let next = expression[start + c.len_utf8()..].chars().next();
 
-        match c {
-            '(' => tokens.push(make_token(
-                TokenType::LEFTPAREN,
-                c.to_string(),
-                Literal::String("".to_string()),
-            )),
-            ')' => tokens.push(make_token(
-                TokenType::RIGHTPAREN,
-                c.to_string(),
-                Literal::String("".to_string()),
-            )),
-            '-' => tokens.push(make_token(
-                TokenType::MINUS,
-                c.to_string(),
-                Literal::String("".to_string()),
-            )),
-            '+' => tokens.push(make_token(
-                TokenType::PLUS,
-                c.to_string(),
-                Literal::String("".to_string()),
-            )),
-            '/' => tokens.push(make_token(
-                TokenType::SLASH,
-                c.to_string(),
-                Literal::String("".to_string()),
-            )),
-            '*' => tokens.push(make_token(
-                TokenType::STAR,
-                c.to_string(),
-                Literal::String("".to_string()),
-            )),
-            '|' => tokens.push(make_token(
-                TokenType::OR,
-                c.to_string(),
-                Literal::String("".to_string()),
-            )),
-            '&' => tokens.push(make_token(
-                TokenType::AND,
-                c.to_string(),
-                Literal::String("".to_string()),
-            )),
-            '%' => tokens.push(make_token(
-                TokenType::MOD,
-                c.to_string(),
-                Literal::String("".to_string()),
-            )),
-            '!' => {
-                current += 1;
-
-                match expression.as_bytes()[current] as char {
-                    '=' => tokens.push(make_token(
-                        TokenType::BANGEQUAL,
-                        c.to_string(),
-                        Literal::String("".to_string()),
-                    )),
-                    _ => tokens.push(make_token(
-                        TokenType::BANG,
-                        c.to_string(),
-                        Literal::String("".to_string()),
-                    )),
-                }
+        let (token, current) = match c {
+            '"' => handle_string(expression, start, &mut errors),
+            '#' if next == Some('r') => handle_string(expression, start, &mut errors),
+            // A lone `|`, followed by whitespace, a closing delimiter or
+            // nothing, is a symbol: the `|` procedure
+            '|' if next.is_some_and(|next| !next.is_whitespace() && !")]}".contains(next)) => {
+                handle_symbol(expression, start, &mut errors)
             }
-            '=' => {
-                current += 1;
+            _ if is_symbol_char(c) && c != '#' => handle_atom(expression, start, &keywords),
+            _ => {
+                let mut current = start + c.len_utf8();
+                let token_type = match c {
+                    '(' => TokenType::LEFTPAREN,
+                    ')' => TokenType::RIGHTPAREN,
+                    '#' if next == Some('(') => {
+                        current += 1;
+                        TokenType::HASHPAREN
+                    }
+                    '[' => TokenType::LEFTBRACKET,
+                    ']' => TokenType::RIGHTBRACKET,
+                    '{' => TokenType::LEFTBRACE,
+                    '}' => TokenType::RIGHTBRACE,
+                    '\'' => TokenType::QUOTE,
+                    '`' => TokenType::QUASIQUOTE,
+                    ',' if next == Some('@') => {
+                        current += 1;
+                        TokenType::UNQUOTESPLICING
+                    }
+                    ',' => TokenType::UNQUOTE,
+                    ';' => {
+                        current = expression[start..]
+                            .find('\n')
+                            .map_or(expression.len(), |newline| start + newline);
+                        TokenType::COMMENT
+                    }
+                    '#' if next == Some('|') => {
+                        current = skip_block_comment(expression, start).unwrap_or_else(|error| {
+                            errors.push(error);
+                            expression.len()
+                        });
+                        TokenType::COMMENT
+                    }
+                    '#' if next == Some(';') => {
+                        current += 1;
+                        TokenType::DATUMCOMMENT
+                    }
+                    _ if c.is_whitespace() => {
+                        current = scan_while(expression, start, char::is_whitespace);
+                        TokenType::WHITESPACE
+                    }
+                    _ => {
+                        errors.push(SyntaxError::at(
+                            expression,
+                            Span {
+                                start,
+                                end: current,
+                            },
+                            format!("Unexpected character {:?}", c),
+                        ));
+                        TokenType::ERROR
+                    }
+                };
 
-                match expression.as_bytes()[current] as char {
-                    '=' => tokens.push(make_token(
-                        TokenType::EQUALEQUAL,
-                        c.to_string(),
-                        Literal::String("".to_string()),
-                    )),
-                    _ => tokens.push(make_token(
-                        TokenType::EQUAL,
-                        c.to_string(),
+                (
+                    make_token(
+                        token_type,
+                        expression[start..current].to_string(),
                         Literal::String("".to_string()),
-                    )),
-                }
+                        Span {
+                            start,
+                            end: current,
+                        },
+                    ),
+                    current,
+                )
             }
-            '>' => {
-                current += 1;
+        };
 
-                match expression.as_bytes()[current] as char {
-                    '=' => tokens.push(make_token(
-                        TokenType::GREATEREQUAL,
-                        c.to_string(),
-                        Literal::String("".to_string()),
-                    )),
-                    _ => tokens.push(make_token(
-                        TokenType::GREATER,
-                        c.to_string(),
-                        Literal::String("".to_string()),
-                    )),
-                }
-            }
-            '<' => {
-                current += 1;
+        tokens.push(token);
+        start = current;
+    }
 
-                match expression.as_bytes()[current] as char {
-                    '=' => tokens.push(make_token(
-                        TokenType::LESSEREQUAL,
-                        c.to_string(),
-                        Literal::String("".to_string()),
-                    )),
-                    _ => tokens.push(make_token(
-                        TokenType::LESSER,
-                        c.to_string(),
-                        Literal::String("".to_string()),
-                    )),
-                }
-            }
-            ';' => {
-                while c != '\n' {
-                    start += 1;
-                }
-            }
-            '"' => {
-                let tup = handle_string(&expression, start);
-                tokens.push(tup.0);
-                start = tup.1;
-            }
-            '\r' => start += 1,
+    (tokens, errors)
+}
 
-            _ => {
-                if c.is_digit(10) {
-                    let tup = handle_digit(&expression, start);
-                    tokens.push(tup.0);
-                    start = tup.1 - 1;
-                } else if c.is_alphabetic() {
-                    let tup = handle_alpha(&expression, start, keywords.clone());
-                    tokens.push(tup.0);
-                    start = tup.1 - 1;
-                } else {
-                    start += 1;
-                    current = start;
-                    continue;
+/// Byte offset just past the (possibly nested) `#| ... |#` block at `start`.
+fn skip_block_comment(expression: &str, start: usize) -> Result<usize, SyntaxError> {
+    let mut nesting = 0;
+    let mut chars = expression[start..].char_indices().peekable();
+
+    while let Some((offset, c)) = chars.next() {
+        match (c, chars.peek()) {
+            ('#', Some((_, '|'))) => {
+                chars.next();
+                nesting += 1;
+            }
+            ('|', Some((_, '#'))) => {
+                chars.next();
+                nesting -= 1;
+                if nesting == 0 {
+                    return Ok(start + offset + 2);
                 }
             }
+            _ => {}
         }
-
-        start += 1;
-        current = start;
     }
 
-    tokens
+    Err(SyntaxError::at(
+        expression,
+        Span {
+            start,
+            end: expression.len(),
+        },
+        "Unterminated block comment".to_string(),
+    ))
 }
 
-pub fn make_token(token_type: TokenType, lexeme: String, literal: Literal) -> tokens::Token {
+pub fn make_token(
+    token_type: TokenType,
+    lexeme: String,
+    literal: Literal,
+    span: Span,
+) -> tokens::Token {
     tokens::Token {
         token_type,
         lexeme,
         literal,
+        span,
     }
 }
 
-pub fn handle_string(expression: &str, start: usize) -> (Token, usize) {
-    let mut current = start + 1;
-    let mut buffer: String = String::from('"');
-
-    while expression.as_bytes()[current] as char != '"' {
-        buffer.push(expression.as_bytes()[current] as char);
-        current += 1;
-    }
-
-    buffer.push('"');
+/// Lexes the string literal starting at byte `start` of `expression`; returns
+/// the token and the byte offset just past it. Malformed escapes and a missing
+/// closing quote are added to `errors`.
+pub fn handle_string(
+    expression: &str,
+    start: usize,
+    errors: &mut Vec<SyntaxError>,
+) -> (Token, usize) {
+    let (contents, end) = scan_string_recovering(expression, start, errors);
 
     (
         make_token(
             TokenType::STRING,
-            buffer.clone(),
-            Literal::String(buffer[1..buffer.clone().len() - 1].to_string()),
+            expression[start..end].to_string(),
+            Literal::String(contents),
+            Span { start, end },
         ),
-        current,
+        end,
     )
 }
 
-fn handle_digit(expression: &str, start: usize) -> (Token, usize) {
-    let mut current = start;
-    let mut buffer: String = String::from('"');
-
-    while (expression.as_bytes()[current] as char).is_digit(10) {
-        buffer.push(expression.as_bytes()[current] as char);
-        current += 1;
-    }
-
-    buffer.push('"');
+/// Lexes the `|...|` symbol starting at byte `start` of `expression`, like
+/// `handle_string`.
+fn handle_symbol(expression: &str, start: usize, errors: &mut Vec<SyntaxError>) -> (Token, usize) {
+    let (name, end) = scan_symbol(expression, start, errors);
 
     (
         make_token(
-            TokenType::NUMBER,
-            buffer.clone(),
-            Literal::Number(buffer[1..buffer.clone().len() - 1].parse().unwrap()),
+            TokenType::IDENTIFIER,
+            expression[start..end].to_string(),
+            Literal::String(name),
+            Span { start, end },
         ),
-        current,
+        end,
     )
 }
 
-fn handle_alpha(
+/// Malformed source text: the span it covers, and the 1-based line and
+/// column where that starts.
+#[derive(Debug, PartialEq)]
+pub struct SyntaxError {
+    pub message: String,
+    pub span: Span,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SyntaxError {
+    pub fn at(expression: &str, span: Span, message: String) -> Self {
+        let (line, column) = line_and_column(expression, span.start);
+
+        Self {
+            message,
+            span,
+            line,
+            column,
+        }
+    }
+}
+
+/// The 1-based line and column of byte `offset` in `expression`.
+pub fn line_and_column(expression: &str, offset: usize) -> (usize, usize) {
+    let before = &expression[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before[before.rfind('\n').map_or(0, |newline| newline + 1)..]
+        .chars()
+        .count()
+        + 1;
+
+    (line, column)
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Reads the string literal at byte `start`, returning its contents and the
+/// byte index just past it.
+///
+/// `"..."` strings may span lines and understand the escapes `\n`, `\t`,
+/// `\r`, `\0`, `\\`, `\"` and `\u{1F600}`; a backslash at the end of a line
+/// skips the line break and the next line's indentation. Raw strings
+/// `#r"..."` take their contents verbatim, and as many `#` as needed can
+/// surround the quotes so the contents may hold `"`: `#r#"say "hi""#`.
+///
+/// Every malformed escape is added to `errors` and reading goes on; an
+/// unterminated literal runs to the end of `expression`.
+pub fn scan_string_recovering(
     expression: &str,
     start: usize,
-    keywords: HashMap<&'static str, TokenType>,
-) -> (Token, usize) {
-    let mut current = start;
-    let mut buffer: String = "".to_string();
+    errors: &mut Vec<SyntaxError>,
+) -> (String, usize) {
+    let end = expression.len();
+    let unterminated = || {
+        SyntaxError::at(
+            expression,
+            Span { start, end },
+            "Unterminated string literal".to_string(),
+        )
+    };
+
+    if let Some(raw) = expression[start..].strip_prefix("#r") {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let open = start + 2 + hashes;
+
+        if !expression[open..].starts_with('"') {
+            errors.push(SyntaxError::at(
+                expression,
+                Span { start, end: open },
+                "Expected \" to open the raw string".to_string(),
+            ));
+            return (String::new(), open);
+        }
 
-    while (expression.as_bytes()[current] as char).is_alphanumeric() {
-        buffer.push(expression.as_bytes()[current] as char);
-        current += 1;
+        let close = format!("\"{}", "#".repeat(hashes));
+        let body = open + 1;
+        return match expression[body..].find(&close) {
+            Some(length) => (
+                expression[body..body + length].to_string(),
+                body + length + close.len(),
+            ),
+            None => {
+                errors.push(unterminated());
+                (expression[body..].to_string(), end)
+            }
+        };
     }
 
-    let mut token = TokenType::IDENTIFIER;
+    let mut contents = String::new();
+    let mut chars = expression[start + 1..].char_indices().peekable();
+
+    while let Some((offset, c)) = chars.next() {
+        let position = start + 1 + offset;
+
+        match c {
+            '"' => return (contents, position + 1),
+            '\\' => {
+                let escape = match chars.next() {
+                    Some((_, escape)) => escape,
+                    None => break,
+                };
+
+                match escape {
+                    'n' => contents.push('\n'),
+                    't' => contents.push('\t'),
+                    'r' => contents.push('\r'),
+                    '0' => contents.push('\0'),
+                    '\\' => contents.push('\\'),
+                    '"' => contents.push('"'),
+                    '\n' => while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {},
+                    'u' => {
+                        let digits: String = match chars.next_if(|(_, c)| *c == '{') {
+                            Some(_) => chars
+                                .by_ref()
+                                .map(|(_, c)| c)
+                                .take_while(|c| *c != '}')
+                                .collect(),
+                            None => String::new(),
+                        };
 
-    if keywords.contains_key(buffer.as_str()) {
-        token = keywords[buffer.as_str()];
+                        match u32::from_str_radix(&digits, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                        {
+                            Some(c) => contents.push(c),
+                            None => errors.push(SyntaxError::at(
+                                expression,
+                                Span {
+                                    start: position,
+                                    end: chars.peek().map_or(end, |(offset, _)| start + 1 + offset),
+                                },
+                                format!("Invalid unicode escape \\u{{{}}}", digits),
+                            )),
+                        }
+                    }
+                    other => errors.push(SyntaxError::at(
+                        expression,
+                        Span {
+                            start: position,
+                            end: position + 1 + other.len_utf8(),
+                        },
+                        format!("Unknown escape sequence \\{}", other),
+                    )),
+                }
+            }
+            c => contents.push(c),
+        }
     }
 
-    (
-        make_token(token, buffer, Literal::String("".to_string())),
-        current,
-    )
+    errors.push(unterminated());
+    (contents, end)
+}
+
+/// Reads the `|...|` symbol at byte `start`, returning its name and the byte
+/// index just past it. Between the bars every character stands for itself,
+/// and `\` makes the next one do so too, for `\|` and `\\`; this spells names
+/// that would otherwise read as something else, like `|a b|` or `|12|`. An
+/// unterminated symbol is added to `errors` and runs to the end of
+/// `expression`.
+pub fn scan_symbol(
+    expression: &str,
+    start: usize,
+    errors: &mut Vec<SyntaxError>,
+) -> (String, usize) {
+    let mut name = String::new();
+    let mut chars = expression[start + 1..].char_indices();
+
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '|' => return (name, start + 1 + offset + 1),
+            '\\' => match chars.next() {
+                Some((_, escaped)) => name.push(escaped),
+                None => break,
+            },
+            c => name.push(c),
+        }
+    }
+
+    errors.push(SyntaxError::at(
+        expression,
+        Span {
+            start,
+            end: expression.len(),
+        },
+        "Unterminated symbol".to_string(),
+    ));
+    (name, expression.len())
+}
+
+/// Writes the symbol `name` so that it reads back as that symbol: bare when
+/// it can be, otherwise between bars.
+pub fn write_symbol(name: &str) -> String {
+    let bare = name == "|"
+        || (!name.is_empty()
+            && name.chars().all(is_symbol_char)
+            && !name.starts_with(['#', '|'])
+            && parse_number(name).is_none()
+            && name != "true"
+            && name != "false"
+            && !(name.len() > 1 && name.starts_with(':')));
+
+    if bare {
+        name.to_string()
+    } else {
+        escape_symbol(name)
+    }
+}
+
+/// Writes `name` as a `|...|` symbol. A first character that would leave the
+/// opening `|` on its own is escaped too.
+pub fn escape_symbol(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len() + 2);
+    escaped.push('|');
+    for (index, c) in name.chars().enumerate() {
+        let alone = index == 0 && (c.is_whitespace() || ")]}".contains(c));
+        if c == '|' || c == '\\' || alone {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped.push('|');
+    escaped
+}
+
+/// Writes `contents` back as the body of a `"..."` literal.
+pub fn escape(contents: &str) -> String {
+    let mut escaped = String::with_capacity(contents.len());
+
+    for c in contents.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            c if c.is_control() => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Characters allowed in symbols: anything but whitespace, parentheses,
+/// brackets, braces and the characters with a meaning of their own to the
+/// reader (`"`, `;`, `'`, `` ` `` and `,`). This admits names like
+/// `string->list`, `null?`, `set!`, `*global*`, `<=>`, `λ` and operators
+/// like `+` or `<=`.
+pub fn is_symbol_char(c: char) -> bool {
+    !c.is_whitespace() && !c.is_control() && !"()[]{}\";'`,".contains(c)
+}
+
+/// Byte offset just past the run of characters from `start` satisfying `accept`.
+fn scan_while(expression: &str, start: usize, accept: impl Fn(char) -> bool) -> usize {
+    expression[start..]
+        .char_indices()
+        .find(|(_, c)| !accept(*c))
+        .map_or(expression.len(), |(offset, _)| start + offset)
+}
+
+/// The value of `lexeme` if it is a number: digits with an optional sign and
+/// decimal point, like `42`, `-3` or `.5`. A lone `+`, `-` or `.` is a symbol.
+pub fn parse_number(lexeme: &str) -> Option<f32> {
+    let unsigned = lexeme.strip_prefix(['+', '-']).unwrap_or(lexeme);
+    let digits = unsigned.strip_prefix('.').unwrap_or(unsigned);
+
+    if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        lexeme.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Lexes the number or symbol starting at byte `start`.
+fn handle_atom(
+    expression: &str,
+    start: usize,
+    keywords: &HashMap<&'static str, TokenType>,
+) -> (Token, usize) {
+    let current = scan_while(expression, start, is_symbol_char);
+    let buffer = &expression[start..current];
+
+    let token = match parse_number(buffer) {
+        Some(number) => make_token(
+            TokenType::NUMBER,
+            buffer.to_string(),
+            Literal::Number(number),
+            Span {
+                start,
+                end: current,
+            },
+        ),
+        None => make_token(
+            keywords
+                .get(buffer)
+                .copied()
+                .unwrap_or(TokenType::IDENTIFIER),
+            buffer.to_string(),
+            Literal::String("".to_string()),
+            Span {
+                start,
+                end: current,
+            },
+        ),
+    };
+
+    (token, current)
 }
 
 fn get_keywords() -> HashMap<&'static str, TokenType> {
@@ -250,3 +516,289 @@ fn get_keywords() -> HashMap<&'static str, TokenType> {
 
     keywords
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ParserResult;
+    use crate::syntax;
+
+    /// The tokens of `expression` other than trivia.
+    fn tokens(expression: &str) -> Vec<Token> {
+        lex(expression)
+            .0
+            .into_iter()
+            .filter(|token| !token.token_type.is_trivia())
+            .collect()
+    }
+
+    fn lexemes(expression: &str) -> Vec<(TokenType, String)> {
+        tokens(expression)
+            .into_iter()
+            .map(|token| (token.token_type, token.lexeme))
+            .collect()
+    }
+
+    fn first_error(expression: &str) -> Option<String> {
+        lex(expression).1.first().map(ToString::to_string)
+    }
+
+    fn contents(literal: &str) -> Result<String, SyntaxError> {
+        let mut errors = vec![];
+        let (contents, _) = scan_string_recovering(literal, 0, &mut errors);
+
+        match errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(contents),
+        }
+    }
+
+    #[test]
+    fn escape_sequences() {
+        assert_eq!(
+            contents(r#""say \"hi\"\n\t\\ \u{1F600}""#),
+            Ok("say \"hi\"\n\t\\ \u{1F600}".to_string())
+        );
+        assert_eq!(
+            contents(r#""\q""#),
+            Err(SyntaxError {
+                message: "Unknown escape sequence \\q".to_string(),
+                span: Span { start: 1, end: 3 },
+                line: 1,
+                column: 2,
+            })
+        );
+        assert!(contents(r#""\u{110000}""#).is_err());
+    }
+
+    #[test]
+    fn multi_line_strings() {
+        assert_eq!(contents("\"one\ntwo\""), Ok("one\ntwo".to_string()));
+        assert_eq!(
+            contents("\"one \\\n        two\""),
+            Ok("one two".to_string())
+        );
+    }
+
+    #[test]
+    fn raw_strings() {
+        assert_eq!(contents(r#"#r"C:\path\n""#), Ok(r"C:\path\n".to_string()));
+        assert_eq!(
+            contents(r###"#r##"say "#hi"#"##"###),
+            Ok(r##"say "#hi"#"##.to_string())
+        );
+    }
+
+    #[test]
+    fn unterminated_strings_point_at_the_opening_quote() {
+        assert_eq!(
+            first_error("(define s 1)\n  (print \"oops)\n"),
+            Some("2:10: Unterminated string literal".to_string())
+        );
+        assert!(contents(r#"#r#"no end""#).is_err());
+    }
+
+    #[test]
+    fn escape_round_trips() {
+        let text = "tab\t \"quote\" back\\slash\nbell\u{7}";
+
+        assert_eq!(
+            contents(&format!("\"{}\"", escape(text))),
+            Ok(text.to_string())
+        );
+    }
+
+    #[test]
+    fn multi_byte_identifiers_and_strings() {
+        let tokens = tokens("(define λ \"héllo 🌍\")");
+
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| (token.token_type, token.lexeme.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (TokenType::LEFTPAREN, "("),
+                (TokenType::FUN, "define"),
+                (TokenType::IDENTIFIER, "λ"),
+                (TokenType::STRING, "\"héllo 🌍\""),
+                (TokenType::RIGHTPAREN, ")"),
+            ]
+        );
+        assert_eq!(tokens[3].literal, Literal::String("héllo 🌍".to_string()));
+    }
+
+    #[test]
+    fn multi_byte_comments_and_numbers() {
+        assert_eq!(
+            lexemes("; ünïcödé — comment\n(café 12)"),
+            vec![
+                (TokenType::LEFTPAREN, "(".to_string()),
+                (TokenType::IDENTIFIER, "café".to_string()),
+                (TokenType::NUMBER, "12".to_string()),
+                (TokenType::RIGHTPAREN, ")".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn operators_are_ordinary_symbols() {
+        assert_eq!(
+            lexemes("(!= ö 1) >"),
+            vec![
+                (TokenType::LEFTPAREN, "(".to_string()),
+                (TokenType::IDENTIFIER, "!=".to_string()),
+                (TokenType::IDENTIFIER, "ö".to_string()),
+                (TokenType::NUMBER, "1".to_string()),
+                (TokenType::RIGHTPAREN, ")".to_string()),
+                (TokenType::IDENTIFIER, ">".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lisp_symbol_syntax() {
+        let symbols = "string->list null? set! *global* my-var <=> + - ... 1+ a.b";
+
+        assert!(lexemes(symbols).iter().zip(symbols.split(' ')).all(
+            |((token_type, lexeme), expected)| {
+                *token_type == TokenType::IDENTIFIER && lexeme == expected
+            }
+        ));
+        assert_eq!(lexemes(symbols).len(), 11);
+    }
+
+    #[test]
+    fn signed_and_fractional_numbers() {
+        let tokens = tokens("-5 +3 .5 -0.25 12");
+
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| &token.literal)
+                .collect::<Vec<_>>(),
+            vec![
+                &Literal::Number(-5.0),
+                &Literal::Number(3.0),
+                &Literal::Number(0.5),
+                &Literal::Number(-0.25),
+                &Literal::Number(12.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_characters_outside_symbols() {
+        assert_eq!(
+            first_error("(a #b)"),
+            Some("1:4: Unexpected character '#'".to_string())
+        );
+    }
+
+    #[test]
+    fn recovers_from_errors() {
+        let source = "a # b\n\"x\\u{zz}\" #| never closed";
+        let (_, errors) = lex(source);
+
+        assert_eq!(
+            tokens(source)
+                .iter()
+                .map(|token| token.lexeme.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "b", "\"x\\u{zz}\""]
+        );
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "1:3: Unexpected character '#'",
+                "2:3: Invalid unicode escape \\u{zz}",
+                "2:11: Unterminated block comment",
+            ]
+        );
+    }
+
+    #[test]
+    fn escaped_symbols() {
+        let tokens = tokens(r"(| a) |a b| || |x\|y\\|");
+
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| token.lexeme.as_str())
+                .collect::<Vec<_>>(),
+            vec!["(", "|", "a", ")", "|a b|", "||", r"|x\|y\\|"]
+        );
+        assert_eq!(tokens[6].literal, Literal::String(r"x|y\".to_string()));
+        assert_eq!(
+            first_error("(a |b c)"),
+            Some("1:4: Unterminated symbol".to_string())
+        );
+    }
+
+    #[test]
+    fn symbols_are_written_bare_only_when_they_read_back() {
+        let written = [
+            "plus", "a|b", "|", "a b", "12", "true", ":a", "#:g1", "", " x", r"x|\",
+        ]
+        .map(write_symbol);
+
+        assert_eq!(
+            written,
+            [
+                "plus", "a|b", "|", "|a b|", "|12|", "|true|", "|:a|", "|#:g1|", "||", r"|\ x|",
+                r"x|\"
+            ]
+        );
+    }
+
+    #[test]
+    fn line_comments() {
+        assert_eq!(
+            lexemes("; a comment (with parens)\na ; trailing\nb"),
+            vec![
+                (TokenType::IDENTIFIER, "a".to_string()),
+                (TokenType::IDENTIFIER, "b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_block_comments() {
+        assert_eq!(
+            lexemes("a #| outer #| inner |# still \"commented\" |# b #|x|#c"),
+            vec![
+                (TokenType::IDENTIFIER, "a".to_string()),
+                (TokenType::IDENTIFIER, "b".to_string()),
+                (TokenType::IDENTIFIER, "c".to_string()),
+            ]
+        );
+        assert_eq!(
+            first_error("a\n  #| #| |# never closed"),
+            Some("2:3: Unterminated block comment".to_string())
+        );
+    }
+
+    #[test]
+    fn datum_comments_drop_the_next_datum() {
+        let kept = |source| -> Vec<String> {
+            let (tree, errors) = syntax::parse(source);
+            assert!(errors.is_empty(), "{:?}", errors);
+            tree.data().iter().map(ParserResult::to_source).collect()
+        };
+        let first_error = |source| syntax::parse(source).1.first().map(ToString::to_string);
+
+        assert_eq!(
+            lexemes("#; a")[0],
+            (TokenType::DATUMCOMMENT, "#;".to_string())
+        );
+        assert_eq!(kept("#;(ignored (list)) kept"), vec!["kept"]);
+        assert_eq!(kept("(a #; b c)"), vec!["(a c)"]);
+        assert_eq!(kept("#; #; a b c"), vec!["c"]);
+        assert_eq!(kept("(f #;\"skipped\" 1 #;(x)) 2"), vec!["(f 1)", "2"]);
+        assert_eq!(
+            first_error("(a #;)"),
+            Some("1:4: Expected a datum after #;".to_string())
+        );
+        assert!(first_error("a #;").is_some());
+    }
+}
@@ -1,37 +1,67 @@
+use std::fmt;
+
 #[derive(Debug, PartialEq)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub literal: Literal,
+    pub span: Span,
+}
+
+/// Byte range of a token in the source text.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The 1-based line and column a datum starts at, counting columns in
+/// characters.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum Literal {
     String(String),
-    Number(u32),
+    Number(f32),
 }
 
-#[derive(Debug, PartialEq)]
+/// `+`, `<=` and other operators are plain `IDENTIFIER`s: they name
+/// procedures like any other symbol.
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum TokenType {
     LEFTPAREN,
     RIGHTPAREN,
-    MINUS,
-    PLUS,
-    SLASH,
-    STAR,
-    OR,
-    AND,
-    MOD,
+    /// `#(` and `[` open a vector, closed by `)` and `]` respectively
+    HASHPAREN,
+    LEFTBRACKET,
+    RIGHTBRACKET,
+    /// `{` and `}` around a map
+    LEFTBRACE,
+    RIGHTBRACE,
+    /// The reader shorthands ' ` , and ,@
+    QUOTE,
+    QUASIQUOTE,
+    UNQUOTE,
+    UNQUOTESPLICING,
 
-    BANG,
-    BANGEQUAL,
-    STRINGEQUAL,
-    EQUAL,
-    EQUALEQUAL,
-    GREATER,
-    GREATEREQUAL,
-    LESSER,
-    LESSEREQUAL,
+    /// Trivia: only the lossless lexer produces these
+    WHITESPACE,
+    /// A `;` line or `#| ... |#` block comment
+    COMMENT,
+    /// The `#;` that comments out the next datum
+    DATUMCOMMENT,
+    /// A character that cannot start a token
+    ERROR,
 
     IDENTIFIER,
     STRING,
@@ -46,39 +76,41 @@ pub enum TokenType {
     LIST,
 }
 
-impl Clone for TokenType {
-    fn clone(&self) -> Self {
+impl TokenType {
+    /// Whitespace, comments and stray characters: text that never becomes part
+    /// of a datum.
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            TokenType::WHITESPACE | TokenType::COMMENT | TokenType::ERROR
+        )
+    }
+
+    /// Starts a list, vector or map.
+    pub fn is_open(self) -> bool {
+        matches!(
+            self,
+            TokenType::LEFTPAREN
+                | TokenType::HASHPAREN
+                | TokenType::LEFTBRACKET
+                | TokenType::LEFTBRACE
+        )
+    }
+
+    /// Ends a list, vector or map.
+    pub fn is_close(self) -> bool {
+        matches!(
+            self,
+            TokenType::RIGHTPAREN | TokenType::RIGHTBRACKET | TokenType::RIGHTBRACE
+        )
+    }
+
+    /// The token that ends what this one starts.
+    pub fn closer(self) -> TokenType {
         match self {
-            Self::LEFTPAREN => Self::LEFTPAREN,
-            Self::RIGHTPAREN => Self::RIGHTPAREN,
-            Self::MINUS => Self::MINUS,
-            Self::PLUS => Self::PLUS,
-            Self::SLASH => Self::SLASH,
-            Self::STAR => Self::STAR,
-            Self::OR => Self::OR,
-            Self::AND => Self::AND,
-            Self::MOD => Self::MOD,
-            Self::BANG => Self::BANG,
-            Self::BANGEQUAL => Self::BANGEQUAL,
-            Self::STRINGEQUAL => Self::STRINGEQUAL,
-            Self::EQUAL => Self::EQUAL,
-            Self::EQUALEQUAL => Self::EQUALEQUAL,
-            Self::GREATER => Self::GREATER,
-            Self::GREATEREQUAL => Self::GREATEREQUAL,
-            Self::LESSER => Self::LESSER,
-            Self::LESSEREQUAL => Self::LESSEREQUAL,
-            Self::IDENTIFIER => Self::IDENTIFIER,
-            Self::STRING => Self::STRING,
-            Self::NUMBER => Self::NUMBER,
-            Self::TRUE => Self::TRUE,
-            Self::FALSE => Self::FALSE,
-            Self::COND => Self::COND,
-            Self::IF => Self::IF,
-            Self::ELSE => Self::ELSE,
-            Self::FUN => Self::FUN,
-            Self::LIST => Self::LIST,
+            TokenType::LEFTBRACKET => TokenType::RIGHTBRACKET,
+            TokenType::LEFTBRACE => TokenType::RIGHTBRACE,
+            _ => TokenType::RIGHTPAREN,
         }
     }
 }
-
-impl Copy for TokenType {}
@@ -0,0 +1,313 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    hash::{Hash, Hasher},
+    mem,
+    rc::Rc,
+};
+
+use crate::interpreter::{Apply, Environment, InterpretError};
+use crate::lower::Lambda;
+use crate::map::Map;
+use crate::parser::*;
+use crate::printer;
+use crate::symbol::Symbol;
+
+/// A runtime value produced by the `Interpreter`.
+#[derive(Clone)]
+pub enum Value {
+    Number(f32),
+    Bool(bool),
+    Str(String),
+    Symbol(Symbol),
+    /// `:name`, which evaluates to itself. Holds the name without the colon.
+    Keyword(Symbol),
+    List(Vec<Value>),
+    /// Fixed-length and indexed in constant time. Vectors are shared, not
+    /// copied, so `vector-set!` is seen through every reference to one.
+    Vector(Rc<RefCell<Vec<Value>>>),
+    /// A hash table, shared like a vector. Any value can be a key.
+    Map(Rc<RefCell<Map>>),
+    Procedure(Rc<Closure>),
+    Primitive(Rc<Primitive>),
+    Port(Rc<Port>),
+    /// Result of forms evaluated only for their effect, like `define` and `set!`.
+    Unspecified,
+}
+
+/// A `lambda` together with the environment it was created in.
+pub struct Closure {
+    pub lambda: Rc<Lambda>,
+    pub environment: Environment,
+}
+
+pub type Builtin = dyn Fn(&[Value], &mut dyn Apply) -> Result<Value, InterpretError>;
+
+/// A procedure implemented in Rust. Besides its arguments it gets an `Apply`
+/// to call the procedures it is passed.
+pub struct Primitive {
+    pub name: String,
+    pub function: Box<Builtin>,
+}
+
+impl Value {
+    /// Everything except `false` counts as true.
+    pub fn is_true(&self) -> bool {
+        !matches!(self, Value::Bool(false))
+    }
+
+    pub fn symbol(name: &str) -> Value {
+        Value::Symbol(Symbol::new(name))
+    }
+
+    pub fn vector(items: Vec<Value>) -> Value {
+        Value::Vector(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn map(map: Map) -> Value {
+        Value::Map(Rc::new(RefCell::new(map)))
+    }
+
+    /// A copy sharing no vectors or maps with this value. Meant for literals,
+    /// which never contain themselves.
+    pub fn copy(&self) -> Value {
+        match self {
+            Value::List(items) => Value::List(items.iter().map(Value::copy).collect()),
+            Value::Vector(items) => Value::vector(items.borrow().iter().map(Value::copy).collect()),
+            Value::Map(map) => Value::map(
+                map.borrow()
+                    .iter()
+                    .map(|(key, value)| (key.copy(), value.copy()))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    /// The value a quoted form evaluates to.
+    pub fn from_datum(datum: &ParserResult) -> Value {
+        match datum {
+            ParserResult::Atom(element) => match element.kind {
+                Kind::Bool => Value::Bool(element.value == "true"),
+                Kind::String => Value::Str(element.value.clone()),
+                Kind::Literal => match element.value.parse() {
+                    Ok(number) => Value::Number(number),
+                    Err(_) => Value::symbol(&element.value),
+                },
+                Kind::Keyword => Value::Keyword(Symbol::new(&element.value[1..])),
+                _ => Value::symbol(&element.value),
+            },
+            ParserResult::Expression(items) => {
+                Value::List(items.iter().map(Value::from_datum).collect())
+            }
+            ParserResult::Vector(items) => {
+                Value::vector(items.iter().map(Value::from_datum).collect())
+            }
+            ParserResult::Map(items) => Value::map(
+                items
+                    .chunks(2)
+                    .map(|entry| {
+                        let value = entry.get(1).map_or(Value::Unspecified, Value::from_datum);
+                        (Value::from_datum(&entry[0]), value)
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The form that quotes back to this value, the inverse of `from_datum`.
+    /// Procedures, ports and the unspecified value have none, and neither do
+    /// vectors and maps that contain themselves.
+    pub fn to_datum(&self) -> Option<ParserResult> {
+        self.to_datum_within(&mut vec![])
+    }
+
+    /// `to_datum`, given the vectors and maps being converted further out.
+    fn to_datum_within(&self, outer: &mut Vec<*const ()>) -> Option<ParserResult> {
+        let atom = |kind, value| {
+            Some(ParserResult::Atom(Element {
+                kind,
+                value,
+                position: None,
+            }))
+        };
+
+        match self {
+            Value::Number(number) => atom(Kind::Literal, number.to_string()),
+            Value::Bool(boolean) => atom(Kind::Bool, boolean.to_string()),
+            Value::Str(string) => atom(Kind::String, string.clone()),
+            Value::Symbol(symbol) => atom(classify(symbol.name()), symbol.name().to_string()),
+            Value::Keyword(keyword) => atom(Kind::Keyword, format!(":{}", keyword.name())),
+            Value::List(items) => Some(ParserResult::Expression(data(items.iter(), outer)?)),
+            Value::Vector(items) => within(Rc::as_ptr(items).cast(), outer, |outer| {
+                Some(ParserResult::Vector(data(items.borrow().iter(), outer)?))
+            }),
+            Value::Map(map) => within(Rc::as_ptr(map).cast(), outer, |outer| {
+                let map = map.borrow();
+                let entries = map.iter().flat_map(|(key, value)| [key, value]);
+                Some(ParserResult::Map(data(entries, outer)?))
+            }),
+            Value::Procedure(_) | Value::Primitive(_) | Value::Port(_) | Value::Unspecified => None,
+        }
+    }
+}
+
+fn data<'a>(
+    items: impl Iterator<Item = &'a Value>,
+    outer: &mut Vec<*const ()>,
+) -> Option<Vec<ParserResult>> {
+    items.map(|item| item.to_datum_within(outer)).collect()
+}
+
+/// Converts the container at `address` with `convert`, unless it is already
+/// being converted further out.
+fn within(
+    address: *const (),
+    outer: &mut Vec<*const ()>,
+    convert: impl FnOnce(&mut Vec<*const ()>) -> Option<ParserResult>,
+) -> Option<ParserResult> {
+    if outer.contains(&address) {
+        return None;
+    }
+
+    outer.push(address);
+    let datum = convert(outer);
+    outer.pop();
+    datum
+}
+
+/// Structural: lists, vectors and maps are equal when their contents are,
+/// even when they contain themselves.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        equal(self, other, &mut vec![])
+    }
+}
+
+/// The pairs of vectors or maps being compared, by address.
+type Assumed = Vec<(*const (), *const ())>;
+
+fn equal(a: &Value, b: &Value, assumed: &mut Assumed) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x == y,
+        (Value::Bool(x), Value::Bool(y)) => x == y,
+        (Value::Str(x), Value::Str(y)) => x == y,
+        (Value::Symbol(x), Value::Symbol(y)) => x == y,
+        (Value::Keyword(x), Value::Keyword(y)) => x == y,
+        (Value::List(x), Value::List(y)) => equal_items(x, y, assumed),
+        (Value::Vector(x), Value::Vector(y)) => assuming(
+            Rc::as_ptr(x).cast(),
+            Rc::as_ptr(y).cast(),
+            assumed,
+            |assumed| equal_items(&x.borrow(), &y.borrow(), assumed),
+        ),
+        (Value::Map(x), Value::Map(y)) => assuming(
+            Rc::as_ptr(x).cast(),
+            Rc::as_ptr(y).cast(),
+            assumed,
+            |assumed| {
+                x.borrow()
+                    .same_entries(&y.borrow(), |x, y| equal(x, y, assumed))
+            },
+        ),
+        (Value::Procedure(x), Value::Procedure(y)) => Rc::ptr_eq(x, y),
+        (Value::Primitive(x), Value::Primitive(y)) => Rc::ptr_eq(x, y),
+        (Value::Port(x), Value::Port(y)) => Rc::ptr_eq(x, y),
+        (Value::Unspecified, Value::Unspecified) => true,
+        _ => false,
+    }
+}
+
+fn equal_items(a: &[Value], b: &[Value], assumed: &mut Assumed) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| equal(x, y, assumed))
+}
+
+/// Compares the containers at `a` and `b` with `compare`, unless they are the
+/// same one or already being compared further out. Meeting a pair again means
+/// both sides loop back the same way, so nothing on that path tells them
+/// apart.
+fn assuming(
+    a: *const (),
+    b: *const (),
+    assumed: &mut Assumed,
+    compare: impl FnOnce(&mut Assumed) -> bool,
+) -> bool {
+    if a == b || assumed.contains(&(a, b)) {
+        return true;
+    }
+
+    assumed.push((a, b));
+    let result = compare(assumed);
+    assumed.pop();
+    result
+}
+
+/// How deep hashing looks into lists and vectors. Bounded so that a vector
+/// containing itself still hashes; equal values agree to any depth.
+const HASH_DEPTH: usize = 4;
+
+/// Consistent with `==`: equal values hash the same, so any value can be a
+/// map key. Maps all hash alike, as their entries have no order and a map
+/// may be a key in itself, which changes its size.
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash_to_depth(state, HASH_DEPTH)
+    }
+}
+
+impl Value {
+    fn hash_to_depth<H: Hasher>(&self, state: &mut H, depth: usize) {
+        mem::discriminant(self).hash(state);
+        match self {
+            // -0 == 0, so both hash as 0
+            Value::Number(number) => (number + 0.0).to_bits().hash(state),
+            Value::Bool(boolean) => boolean.hash(state),
+            Value::Str(string) => string.hash(state),
+            Value::Symbol(symbol) | Value::Keyword(symbol) => symbol.hash(state),
+            Value::List(items) => hash_items(items, state, depth),
+            Value::Vector(items) => hash_items(&items.borrow(), state, depth),
+            Value::Procedure(closure) => Rc::as_ptr(closure).hash(state),
+            Value::Primitive(primitive) => Rc::as_ptr(primitive).hash(state),
+            Value::Port(port) => Rc::as_ptr(port).hash(state),
+            Value::Map(_) | Value::Unspecified => {}
+        }
+    }
+}
+
+fn hash_items<H: Hasher>(items: &[Value], state: &mut H, depth: usize) {
+    items.len().hash(state);
+    if depth > 0 {
+        for item in items {
+            item.hash_to_depth(state, depth - 1);
+        }
+    }
+}
+
+/// Values format the way `write` prints them.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", printer::write(self))
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// Where `format` and the output procedures write to.
+pub enum Port {
+    Stdout,
+    /// An in-memory port from `open-output-string`.
+    String(RefCell<String>),
+}
+
+impl Port {
+    pub fn write_str(&self, text: &str) {
+        match self {
+            Port::Stdout => print!("{}", text),
+            Port::String(buffer) => buffer.borrow_mut().push_str(text),
+        }
+    }
+}
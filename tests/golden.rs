@@ -0,0 +1,108 @@
+//! Golden tests: every `tests/golden/NAME.lisp` is run by the `ilisp` binary.
+//! Its standard output must match `NAME.out`, or be empty if there is none.
+//! A program that should fail has a `NAME.err` holding the expected standard
+//! error, and must exit non-zero.
+//!
+//! `BLESS=1 cargo test --test golden` rewrites the expected files from what
+//! the programs print now.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+fn programs() -> Vec<PathBuf> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut programs: Vec<PathBuf> = fs::read_dir(&directory)
+        .expect("Error reading tests/golden")
+        .map(|entry| entry.expect("Error reading tests/golden").path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "lisp")
+        })
+        .collect();
+
+    programs.sort();
+    programs
+}
+
+/// `expected` against `actual`, or a description of how they differ.
+fn compare(path: &Path, expected: &str, actual: &str) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+
+    Some(format!(
+        "{}\n--- expected\n{}--- actual\n{}",
+        path.display(),
+        expected,
+        actual
+    ))
+}
+
+/// Makes `contents` the expected contents of `path`; nothing expected is no
+/// file at all.
+fn bless_file(path: &Path, contents: &str) {
+    let result = match contents.is_empty() {
+        true if path.exists() => fs::remove_file(path),
+        true => Ok(()),
+        false => fs::write(path, contents),
+    };
+    result.unwrap_or_else(|error| panic!("Error blessing {}: {}", path.display(), error));
+}
+
+#[test]
+fn golden_programs() {
+    let bless = env::var_os("BLESS").is_some();
+    let programs = programs();
+    assert!(!programs.is_empty(), "No programs in tests/golden");
+
+    let mut failures = vec![];
+    for program in &programs {
+        let output = Command::new(env!("CARGO_BIN_EXE_ilisp"))
+            .arg(program)
+            .output()
+            .expect("Error running ilisp");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        let out_path = program.with_extension("out");
+        let err_path = program.with_extension("err");
+
+        if bless {
+            bless_file(&out_path, &stdout);
+            bless_file(&err_path, &stderr);
+            continue;
+        }
+
+        let expected_out = fs::read_to_string(&out_path).unwrap_or_default();
+        let expected_err = fs::read_to_string(&err_path).ok();
+
+        failures.extend(compare(&out_path, &expected_out, &stdout));
+        failures.extend(compare(
+            &err_path,
+            expected_err.as_deref().unwrap_or_default(),
+            &stderr,
+        ));
+        if output.status.success() != expected_err.is_none() {
+            failures.push(format!(
+                "{}: expected to {}, but it exited with {}",
+                program.display(),
+                match expected_err {
+                    Some(_) => "fail",
+                    None => "succeed",
+                },
+                output.status
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} golden programs failed:\n\n{}",
+        failures.len(),
+        programs.len(),
+        failures.join("\n")
+    );
+}
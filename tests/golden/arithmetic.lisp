@@ -0,0 +1,10 @@
+(display (+ 1 2 3)) (newline)
+(display (- 10 4 3)) (newline)
+(display (* 2 3 4)) (newline)
+(display (/ 7 2)) (newline)
+(display (< 1 2)) (newline)
+(display (>= 1 2)) (newline)
+(display (= 3 3)) (newline)
+(display (! true)) (newline)
+(display (& true false)) (newline)
+(display (| false true)) (newline)
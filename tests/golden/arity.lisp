@@ -0,0 +1,2 @@
+(define pair (a b) a)
+(pair 1)
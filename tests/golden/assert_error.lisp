@@ -0,0 +1 @@
+(assert-error (+ 1 2))
@@ -0,0 +1,13 @@
+; Assertions work in any program; deftest forms only run under `ilisp test`.
+(define square (n) (* n n))
+
+(deftest never-run
+  (display "tests are skipped")
+  (assert-equal 1 2))
+
+(write (assert-equal 9 (square 3))) (newline)
+(write (assert-equal '(1 "two") '(1 "two"))) (newline)
+(write (assert-error (square))) (newline)
+(write (assert-error (undefined-procedure))) (newline)
+(assert-equal 4 (square 3))
+(display "never printed")
@@ -0,0 +1,11 @@
+; Literals, strings, booleans and identifiers evaluate to themselves or
+; their bindings.
+(write 42) (newline)
+(write -1.5) (newline)
+(write "a \"quoted\" string\n") (newline)
+(display "a \"quoted\" string") (newline)
+(write true) (newline)
+(write false) (newline)
+(define answer 42)
+(write answer) (newline)
+(write +) (newline)
@@ -0,0 +1,27 @@
+; if, cond, when, unless, and and or. Everything but false counts as true.
+(define sign (n)
+  (cond
+    ((< n 0) "negative")
+    ((= n 0) "zero")
+    (else "positive")))
+(display (sign -3)) (newline)
+(display (sign 0)) (newline)
+(display (sign 8)) (newline)
+
+(display (if (< 1 2) "yes" "no")) (newline)
+(display (if 0 "zero is true" "zero is false")) (newline)
+(write (if false 1)) (newline)
+
+(write (cond (false 1) ((+ 1 1)) (else 3))) (newline)
+(write (cond (false 1))) (newline)
+
+(when (< 1 2) (display "when ran") (newline))
+(unless (< 1 2) (display "unless ran") (newline))
+(unless false (display "unless ran") (newline))
+
+(write (and)) (newline)
+(write (and 1 2 3)) (newline)
+(write (and 1 false (undefined-procedure))) (newline)
+(write (or)) (newline)
+(write (or false 2 (undefined-procedure))) (newline)
+(write (or false false)) (newline)
@@ -0,0 +1,19 @@
+; Every shape of define, and set! on globals and captured variables.
+(define x 1)
+(define square (n) (* n n))
+(define cube (n) (* n n n))
+(define twice n (* 2 n))
+(define add (lambda (a b) (+ a b)))
+(display (format "~a ~a ~a ~a ~a" x (square 3) (cube 2) (twice 5) (add 1 2)))
+(newline)
+
+(set! x 10)
+(display x) (newline)
+
+(define make-counter ()
+  (let ((count 0))
+    (lambda () (set! count (+ count 1)) count)))
+(define counter (make-counter))
+(counter)
+(counter)
+(display (counter)) (newline)
@@ -0,0 +1,4 @@
+(display (/ 1 4)) (newline)
+(assert-error (/ 1 0))
+(display "still running") (newline)
+(/ 10 (- 2 2))
@@ -0,0 +1,12 @@
+(display (format "~a and ~s" "display" "write")) (newline)
+(display (format "~a" '(1 "two" three))) (newline)
+(display (format "~s" '(1 "two" three))) (newline)
+(display (format "100~~ sure~%next line")) (newline)
+
+(define port (open-output-string))
+(write "escaped\t" port)
+(display " " port)
+(display 1.5 port)
+(newline port)
+(write (get-output-string port)) (newline)
+(display "to stdout" (current-output-port)) (newline)
@@ -0,0 +1,15 @@
+; Anonymous functions, closures, rest parameters and higher-order calls.
+(display ((lambda (x y) (+ x y)) 1 2)) (newline)
+
+(define make-adder (n) (lambda (x) (+ x n)))
+(define add5 (make-adder 5))
+(display (add5 10)) (newline)
+
+(define compose (f g) (lambda (x) (f (g x))))
+(display ((compose add5 (lambda (x) (* x 2))) 1)) (newline)
+
+(write ((lambda (first . rest) rest) 1 2 3)) (newline)
+(write ((lambda args args) "a" "b")) (newline)
+(write ((lambda args args))) (newline)
+(write (lambda (x) x)) (newline)
+(write make-adder) (newline)
@@ -0,0 +1,16 @@
+(define x "outer")
+(let ((x "inner") (y 2))
+  (display x)
+  (newline)
+  (display y)
+  (newline))
+(display x) (newline)
+
+(let ((x 1))
+  (let ((x (+ x 1)) (y x))
+    (display (format "~a ~a" x y))
+    (newline)))
+
+(write (begin 1 2 3)) (newline)
+(write (begin)) (newline)
+(write (let () 5)) (newline)
@@ -0,0 +1,26 @@
+; Procedural macros, hygienic syntax-rules and quasiquote templates.
+(defmacro swap! (a b)
+  `(let ((tmp ,a))
+     (set! ,a ,b)
+     (set! ,b tmp)))
+
+(define x 1)
+(define y 2)
+(define tmp 3)
+(swap! x y)
+(display (format "~a ~a ~a" x y tmp)) (newline)
+
+(define-macro (unless-zero n . body)
+  `(if (= ,n 0) false (begin ,@body)))
+(display (unless-zero 5 "not zero")) (newline)
+(write (unless-zero 0 "not zero")) (newline)
+
+(define-syntax my-or
+  (syntax-rules ()
+    ((_) false)
+    ((_ e) e)
+    ((_ e rest ...) (let ((t e)) (if t t (my-or rest ...))))))
+(define t 5)
+(display (my-or false t)) (newline)
+
+(write (macroexpand-1 '(swap! p q))) (newline)
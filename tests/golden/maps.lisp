@@ -0,0 +1,20 @@
+(define ages {:alice 31 :bob 27})
+(hash-set! ages :carol 45)
+(hash-set! ages :bob 28)
+(write ages) (newline)
+(write (hash-ref ages :bob)) (newline)
+(write (hash-ref ages :dave 'unknown)) (newline)
+(write (hash-has-key? ages :alice)) (newline)
+(hash-remove! ages :alice)
+(write (hash-keys ages)) (newline)
+(write (hash-values ages)) (newline)
+(write (hash-count ages)) (newline)
+(write (hash->list ages)) (newline)
+(hash-for-each (lambda (name age) (display name) (display " is ") (display age) (newline)) ages)
+(write (hash-map (lambda (name age) (+ age 1)) ages)) (newline)
+(define grid (hash))
+(hash-set! grid [0 1] "wall")
+(write (hash-ref grid (vector 0 1))) (newline)
+(display {"name" "display" :nested {:map (+ 1 2)}}) (newline)
+(write (hash? ages)) (newline)
+(hash-ref ages :alice)
@@ -0,0 +1,7 @@
+(write (quote (a b c))) (newline)
+(write '(1 "two" (three) true)) (newline)
+(write 'symbol) (newline)
+(write ''a) (newline)
+(write '()) (newline)
+(write '(if define lambda)) (newline)
+(display '("display" "strips" "quotes")) (newline)
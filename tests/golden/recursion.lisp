@@ -0,0 +1,10 @@
+; Deep tail calls run in constant space.
+(define fib (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+(display (fib 15)) (newline)
+
+(define count-down (n) (if (= n 0) "done" (count-down (- n 1))))
+(display (count-down 100000)) (newline)
+
+(define even? (n) (if (= n 0) true (odd? (- n 1))))
+(define odd? (n) (if (= n 0) false (even? (- n 1))))
+(display (even? 10001)) (newline)
@@ -0,0 +1,16 @@
+(define s "Hello, World")
+(display (string-length s)) (newline)
+(display (substring s 0 5)) (newline)
+(display (string-append s "!" "!")) (newline)
+(write (string-split "a b  c")) (newline)
+(display (string-join '("x" "y" "z") "-")) (newline)
+(display (string-upcase s)) (newline)
+(display (string-downcase s)) (newline)
+(write (string-trim "  padded  ")) (newline)
+(write (string-contains s "World")) (newline)
+(write (string->number "2.5")) (newline)
+(write (number->string 7)) (newline)
+(write (string=? "a" "a")) (newline)
+(write (string<? "a" "b")) (newline)
+(write (string? s)) (newline)
+(write (string? 1)) (newline)
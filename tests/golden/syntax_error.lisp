@@ -0,0 +1,3 @@
+(display "never printed")
+(define f (x) (+ x 1)
+(f ))) "unterminated
@@ -0,0 +1,5 @@
+(display "before") (newline)
+(define outer () (inner 1))
+(define inner (x) (missing x))
+(outer)
+(display "never printed")
@@ -0,0 +1,13 @@
+(define v (make-vector 3 0))
+(vector-set! v 0 'first)
+(vector-set! v 2 (vector 1 (+ 1 1)))
+(write v) (newline)
+(display #("display" [nested "vector"])) (newline)
+(write [a (+ 1 2)]) (newline)
+(write (vector-ref [10 20 30] 1)) (newline)
+(write (vector-length #())) (newline)
+(write (vector-map (lambda (x) (* x x)) [1 2 3])) (newline)
+(write (vector->list #(1 "two"))) (newline)
+(write (list->vector '(a b))) (newline)
+(write (vector? v)) (newline)
+(vector-ref v 3)
@@ -0,0 +1,138 @@
+//! Reader and printer round trips: whatever `write` prints, `read` turns back
+//! into the same value, and whatever the reader builds, `to_source` prints
+//! back into text that reads as the same tree.
+
+use ilisp::parser::{classify, read, Element, Kind};
+use ilisp::printer::write;
+use ilisp::symbol::Symbol;
+use ilisp::tokenizer::parse_number;
+use ilisp::value::Value;
+use ilisp::ParserResult;
+use proptest::prelude::*;
+
+/// Names that read as symbols when written bare: not numbers, not booleans,
+/// and not starting with `#`, which introduces the reader's own syntax, `:`,
+/// which makes a keyword, or `|`, which starts an escaped symbol.
+fn plain_name() -> impl Strategy<Value = String> {
+    let first = prop::sample::select("abcxyzλ!$%&*+-./<=>?@^_~".chars().collect::<Vec<_>>());
+    let rest = prop::collection::vec(
+        prop::sample::select(
+            "abcxyzλ0123456789!$%&*+-./:<=>?@^_~#|"
+                .chars()
+                .collect::<Vec<_>>(),
+        ),
+        0..8,
+    );
+
+    (first, rest)
+        .prop_map(|(first, rest)| std::iter::once(first).chain(rest).collect::<String>())
+        .prop_filter("numbers and booleans are not symbols", |name| {
+            parse_number(name).is_none() && name != "true" && name != "false"
+        })
+}
+
+/// Any name a symbol can have, including those only `|...|` can spell, like
+/// `a b`, `12`, `true` or the empty name.
+fn symbol_name() -> impl Strategy<Value = String> {
+    prop_oneof![
+        plain_name(),
+        any::<String>(),
+        prop::sample::select(vec!["", "12", "true", ":a", "#:g1", "|", "|>", "a|b", "\\"])
+            .prop_map(str::to_string),
+    ]
+}
+
+/// Any printable datum: finite numbers, booleans, arbitrary strings, symbols,
+/// keywords and nested lists, vectors and maps of them.
+fn datum() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        any::<f32>()
+            .prop_filter("only finite numbers print as numbers", |n| n.is_finite())
+            .prop_map(Value::Number),
+        any::<bool>().prop_map(Value::Bool),
+        any::<String>().prop_map(Value::Str),
+        symbol_name().prop_map(|name| Value::symbol(&name)),
+        plain_name().prop_map(|name| Value::Keyword(Symbol::new(&name))),
+    ];
+
+    leaf.prop_recursive(4, 32, 6, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..6).prop_map(Value::List),
+            prop::collection::vec(inner.clone(), 0..6).prop_map(Value::vector),
+            prop::collection::vec((inner.clone(), inner), 0..4)
+                .prop_map(|entries| Value::map(entries.into_iter().collect())),
+        ]
+    })
+}
+
+/// Trees the reader could have produced.
+fn tree() -> impl Strategy<Value = ParserResult> {
+    let atom = prop_oneof![
+        any::<f32>()
+            .prop_filter("only finite numbers print as numbers", |n| n.is_finite())
+            .prop_map(|n| (Kind::Literal, n.to_string())),
+        any::<String>().prop_map(|s| (Kind::String, s)),
+        plain_name().prop_map(|name| (classify(&name), name)),
+        symbol_name().prop_map(|name| (Kind::Identifier, name)),
+        plain_name().prop_map(|name| (Kind::Keyword, format!(":{}", name))),
+        prop::sample::select(vec!["define", "if", "lambda", "true", "quote"])
+            .prop_map(|name| (classify(name), name.to_string())),
+    ]
+    .prop_map(|(kind, value)| {
+        ParserResult::Atom(Element {
+            kind,
+            value,
+            position: None,
+        })
+    });
+
+    atom.prop_recursive(4, 32, 6, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..6).prop_map(ParserResult::Expression),
+            prop::collection::vec(inner.clone(), 0..6).prop_map(ParserResult::Vector),
+            prop::collection::vec((inner.clone(), inner), 0..4).prop_map(|entries| {
+                ParserResult::Map(
+                    entries
+                        .into_iter()
+                        .flat_map(|(key, value)| [key, value])
+                        .collect(),
+                )
+            }),
+        ]
+    })
+}
+
+proptest! {
+    #[test]
+    fn written_values_read_back(value in datum()) {
+        let text = write(&value);
+        let data = read(&text).unwrap();
+
+        prop_assert_eq!(data.len(), 1, "{} read as {:?}", text, data);
+        prop_assert_eq!(Value::from_datum(&data[0]), value);
+    }
+
+    #[test]
+    fn printed_trees_read_back(tree in tree()) {
+        prop_assert_eq!(read(&tree.to_source()).unwrap(), vec![tree]);
+    }
+
+    #[test]
+    fn several_values_read_back_in_order(values in prop::collection::vec(datum(), 0..5)) {
+        let text: Vec<String> = values.iter().map(write).collect();
+        let data = read(&text.join("\n")).unwrap();
+
+        prop_assert_eq!(data.iter().map(Value::from_datum).collect::<Vec<_>>(), values);
+    }
+}
+
+#[test]
+fn escapes_survive_a_round_trip() {
+    let value = Value::Str("tab\t quote\" backslash\\ newline\n nul\0 bell\u{7} λ".to_string());
+
+    assert_eq!(
+        write(&value),
+        r#""tab\t quote\" backslash\\ newline\n nul\0 bell\u{7} λ""#
+    );
+    assert_eq!(Value::from_datum(&read(&write(&value)).unwrap()[0]), value);
+}